@@ -0,0 +1,33 @@
+//! Serializes bytes as a standard base64 string, with `#[serde(with = "base64_bytes")]`, so that
+//! JSON requests can carry arbitrary binary data.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD.decode(encoded).map_err(D::Error::custom)
+}
+
+/// Serializes a list of byte strings as a list of base64 strings, with
+/// `#[serde(with = "base64_bytes::vec")]`.
+pub mod vec {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(items: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(items.iter().map(|bytes| STANDARD.encode(bytes)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let encoded = Vec::<String>::deserialize(deserializer)?;
+        let decoded = encoded.iter().map(|item| STANDARD.decode(item));
+        decoded.collect::<Result<_, _>>().map_err(D::Error::custom)
+    }
+}
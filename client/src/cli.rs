@@ -0,0 +1,90 @@
+//! Command line interface to encrypt large files to the key of a session, and to decrypt them
+//! with a running orchestrator:
+//!
+//! ```sh
+//! client encrypt <session_id> <input> <output>
+//! client decrypt <session_id> <input> <output>
+//! ```
+
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+};
+
+use super::{env_or, post_req, stream, DecryptReq, DecryptResp, PubKeySetResp, SessionReq};
+
+/// The orchestrator URL, unless `ORCHESTRATOR_URL` is set.
+const DEFAULT_ORCHESTRATOR_URL: &str = "http://127.0.0.1:3001";
+
+/// Runs the command given by the command line arguments, without the program name.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let (command, session_id, input, output) = match args {
+        [command, session_id, input, output] => (command, session_id, input, output),
+        _ => return Err(usage()),
+    };
+    let session_id = session_id.parse()?;
+    let orchestrator = env_or("ORCHESTRATOR_URL", DEFAULT_ORCHESTRATOR_URL.to_string());
+    match command.as_str() {
+        "encrypt" => encrypt(&orchestrator, session_id, input, output).await,
+        "decrypt" => decrypt(&orchestrator, session_id, input, output).await,
+        _ => Err(usage()),
+    }
+}
+
+fn usage() -> Box<dyn Error + Send + Sync> {
+    "Usage: client (encrypt | decrypt) <session_id> <input> <output>".into()
+}
+
+/// Encrypts the input file to the master public key of the session, in chunks of `CHUNK_SIZE`
+/// bytes.
+async fn encrypt(
+    orchestrator: &str,
+    session_id: usize,
+    input: &str,
+    output: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let req = SessionReq { session_id };
+    let resp: PubKeySetResp = post_req(orchestrator, "pub_key_set", &req).await?;
+    let chunk_size = env_or("CHUNK_SIZE", stream::DEFAULT_CHUNK_SIZE);
+
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let pk = resp.pub_key_set.public_key();
+    let len = stream::encrypt(&pk, chunk_size, &mut reader, &mut writer, &mut rng)
+        .map_err(|e| e.to_string())?;
+    println!("Encrypted {} bytes to {}", len, output);
+    Ok(())
+}
+
+/// Has the committee decrypt the data key in the header of the input file, and decrypts the file
+/// with it. The output file is removed if the input has been tampered with or truncated.
+async fn decrypt(
+    orchestrator: &str,
+    session_id: usize,
+    input: &str,
+    output: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let header = stream::StreamHeader::read_from(&mut reader).map_err(|e| e.to_string())?;
+    let req = DecryptReq {
+        session_id,
+        ciphertext: header.key_ciphertext().clone(),
+    };
+    let resp: DecryptResp = post_req(orchestrator, "decrypt", &req).await?;
+    println!("Data key decrypted by nodes {:?}", resp.contributors);
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    match stream::decrypt(&header, &resp.plaintext, &mut reader, &mut writer) {
+        Ok(len) => {
+            println!("Decrypted {} bytes to {}", len, output);
+            Ok(())
+        }
+        Err(e) => {
+            drop(writer);
+            fs::remove_file(output)?;
+            Err(e.to_string().into())
+        }
+    }
+}
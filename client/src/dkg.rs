@@ -7,14 +7,19 @@ use std::sync::Arc;
 use bincode;
 use failure::Fail;
 use rand::{self, Rng};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::hash::Hash;
 use threshold_crypto::{
     error::Error as CryptoError,
-    pairing::{CurveAffine, Field},
-    poly::{BivarCommitment, BivarPoly, Poly},
+    pairing::{
+        bls12_381::{FrRepr, G1Compressed, G1},
+        CurveAffine, CurveProjective, EncodedPoint, Field, PrimeField,
+    },
+    poly::{BivarCommitment, BivarPoly, Commitment, Poly},
     serde_impl::FieldWrap,
-    Fr, G1Affine, PublicKeySet, SecretKeyShare,
+    Ciphertext, DecryptionShare, Fr, G1Affine, IntoFr, PublicKeySet, PublicKeyShare,
+    SecretKeyShare, Signature, SignatureShare,
 };
 /// A peer node's unique identifier.
 pub trait NodeIdT: Eq + Ord + Clone + Debug + Hash + Send + Sync {}
@@ -77,6 +82,53 @@ where
     Arc::new(sec_keys.into_iter().map(to_pub).collect())
 }
 
+/// The smallest quorum accepted unless configured otherwise: a single key share must never be
+/// enough to sign or decrypt.
+pub const DEFAULT_MIN_QUORUM: usize = 2;
+
+/// The time between two beacon rounds unless configured otherwise, in seconds.
+pub const DEFAULT_BEACON_PERIOD_SECS: u64 = 30;
+
+/// The parameters of a `t`-of-`n` key.
+///
+/// Any `threshold + 1` of the `num_nodes` key shares can sign or decrypt, while `threshold` or
+/// fewer shares reveal nothing about the master key. The `threshold` is the degree of the
+/// generated polynomial, as expected by `SyncKeyGen`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ThresholdParams {
+    /// The maximum number of shares that reveal nothing about the master key.
+    pub threshold: usize,
+    /// The number of nodes that receive a key share.
+    pub num_nodes: usize,
+}
+
+impl ThresholdParams {
+    /// Creates the parameters of a key where `threshold + 1` out of `num_nodes` shares are needed.
+    pub fn new(threshold: usize, num_nodes: usize) -> Self {
+        ThresholdParams {
+            threshold,
+            num_nodes,
+        }
+    }
+
+    /// Returns the number of shares needed to sign or decrypt: `threshold + 1`.
+    pub fn quorum(&self) -> usize {
+        self.threshold + 1
+    }
+
+    /// Returns an error if there are not enough nodes to reach the quorum, or if the quorum is
+    /// smaller than `min_quorum`.
+    pub fn validate(&self, min_quorum: usize) -> Result<(), Error> {
+        if self.quorum() > self.num_nodes {
+            return Err(Error::InvalidThreshold(self.threshold, self.num_nodes));
+        }
+        if self.quorum() < min_quorum {
+            return Err(Error::InsecureThreshold(self.quorum(), min_quorum));
+        }
+        Ok(())
+    }
+}
+
 /// A local error while handling an `Ack` or `Part` message, that was not caused by that message
 /// being invalid.
 #[derive(Clone, Eq, PartialEq, Debug, Fail)]
@@ -96,6 +148,49 @@ pub enum Error {
     /// Failed to encrypt message parts for a peer.
     #[fail(display = "Encryption error: {}", _0)]
     Encrypt(String),
+    /// The qualified set doesn't contain more than `threshold` parts.
+    #[fail(display = "The qualified set of {} parts is too small", _0)]
+    QualifiedSetSize(usize),
+    /// A part in the qualified set is not complete yet: more `Ack`s must be handled first.
+    #[fail(display = "Part {} is not complete yet", _0)]
+    IncompletePart(u64),
+    /// The threshold is not below the number of nodes, or it doesn't match the public keys.
+    #[fail(display = "Invalid threshold {} for {} nodes", _0, _1)]
+    InvalidThreshold(usize, usize),
+    /// The quorum `threshold + 1` is smaller than the configured minimum quorum.
+    #[fail(display = "Quorum {} is below the minimum quorum {}", _0, _1)]
+    InsecureThreshold(usize, usize),
+    /// A `RefreshPart` was handled by a key generation that is not a share refresh or resharing.
+    #[fail(display = "Not a share refresh or resharing")]
+    NotRefreshing,
+    /// A current share holder needs its key share to refresh or reshare it.
+    #[fail(display = "Missing the key share to refresh or reshare")]
+    MissingKeyShare,
+    /// The snapshot has a format version we can't restore.
+    #[fail(display = "Unsupported snapshot version {}", _0)]
+    SnapshotVersion(u32),
+    /// The snapshot's secret values can't be decrypted with our secret key, or don't match it.
+    #[fail(display = "Invalid snapshot: {}", _0)]
+    InvalidSnapshot(String),
+    /// A transcript entry doesn't match the hash chain.
+    #[fail(display = "Transcript entry {} doesn't match the hash chain", _0)]
+    InvalidTranscript(usize),
+    /// The label of a `LabelledCiphertext` differs from the expected one.
+    #[fail(display = "The ciphertext label doesn't match")]
+    LabelMismatch,
+    /// A ciphertext is not well formed, or the proof of a `LabelledCiphertext` doesn't match its
+    /// label.
+    #[fail(display = "Invalid ciphertext")]
+    InvalidCiphertext,
+    /// The decryption share with the given index is not well formed.
+    #[fail(display = "Invalid decryption share {}", _0)]
+    InvalidDecryptionShare(usize),
+    /// There are not more than `threshold` decryption shares.
+    #[fail(display = "Only {} decryption shares", _0)]
+    DecryptionShareCount(usize),
+    /// A beacon round doesn't verify, or doesn't follow the previous one.
+    #[fail(display = "Invalid beacon round {}", _0)]
+    InvalidBeaconRound(u64),
 }
 
 impl From<bincode::Error> for Error {
@@ -108,6 +203,10 @@ impl Error {
     fn encrypt<E: ToString>(err: E) -> Error {
         Error::Encrypt(err.to_string())
     }
+
+    fn invalid_snapshot<E: ToString>(err: E) -> Error {
+        Error::InvalidSnapshot(err.to_string())
+    }
 }
 
 /// A submission by a validator for the key generation. It must to be sent to all participating
@@ -128,6 +227,30 @@ impl Debug for Part {
     }
 }
 
+/// A `Part` message of a share refresh or resharing, together with a serialized offset that all
+/// nodes subtract from the constant term of the dealt bivariate polynomial.
+///
+/// In a share refresh, the offset is the constant term itself, which amounts to dealing a
+/// polynomial with a zero constant term: the part re-randomizes the key shares without changing
+/// the master key. In a resharing, the offset turns the constant term into the dealer's key share.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
+pub struct RefreshPart(Part, Vec<u8>);
+
+impl Borrow<Part> for RefreshPart {
+    fn borrow(&self) -> &Part {
+        &self.0
+    }
+}
+
+impl Debug for RefreshPart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RefreshPart")
+            .field(&self.0)
+            .field(&"<constant>")
+            .finish()
+    }
+}
+
 /// A confirmation that we have received and verified a validator's part. It must be sent to
 /// all participating nodes and handled by all of them, including ourselves.
 ///
@@ -145,6 +268,622 @@ impl Debug for Ack {
     }
 }
 
+/// An accusation against the proposer of a `Part` that failed verification. It must be sent to
+/// all participating nodes and judged by all of them, together with the proposer's
+/// `Justification`, if it answers.
+///
+/// The message contains the proposer's index and the offending `Part`.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
+pub struct Complaint(u64, Part);
+
+impl Complaint {
+    /// Returns the index of the accused proposer.
+    pub fn proposer_idx(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Debug for Complaint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Complaint")
+            .field(&self.0)
+            .field(&self.1)
+            .finish()
+    }
+}
+
+/// A proposer's answer to a `Complaint`. It must be sent to all participating nodes.
+///
+/// The message reveals the accuser's row of the proposer's bivariate polynomial, so that
+/// everyone can check it against the commitment. The master key stays secret as long as at most
+/// `threshold` rows of a part are revealed or known to faulty nodes.
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq)]
+pub struct Justification(u64, Poly);
+
+impl Debug for Justification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Justification")
+            .field(&self.0)
+            .field(&format!("<degree {}>", self.1.degree()))
+            .finish()
+    }
+}
+
+/// Our secret share of a generated key.
+///
+/// Unlike a `SecretKeyShare`, it can be refreshed with `SyncKeyGen::refresh`. It must be kept as
+/// secret as the `SecretKeyShare` it converts to.
+#[derive(Clone, Eq, PartialEq)]
+pub struct KeyShare(Fr);
+
+impl Debug for KeyShare {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyShare").field(&"<secret>").finish()
+    }
+}
+
+impl KeyShare {
+    /// Returns the `SecretKeyShare` to sign and decrypt with.
+    pub fn secret_key_share(&self) -> SecretKeyShare {
+        let mut fr = self.0;
+        SecretKeyShare::from_mut(&mut fr)
+    }
+
+    /// Returns our signature share of the message in the given context.
+    pub fn sign(&self, context: SigningContext, msg: &[u8]) -> SignatureShare {
+        self.secret_key_share().sign(context.signed_bytes(msg))
+    }
+
+    /// Encrypts the key share to the given public key, usually our own, so that it can be stored.
+    pub fn encrypt<PK: PublicKey, R: rand::Rng>(
+        &self,
+        pk: &PK,
+        rng: &mut R,
+    ) -> Result<Vec<u8>, Error> {
+        let ser_val = bincode::serialize(&FieldWrap(self.0))?;
+        pk.encrypt(ser_val, rng).map_err(Error::encrypt)
+    }
+
+    /// Decrypts a key share that was encrypted with `encrypt`.
+    pub fn decrypt<SK: SecretKey>(ct: &[u8], sec_key: &SK) -> Result<KeyShare, Error> {
+        let ser_val = sec_key.decrypt(ct).map_err(Error::invalid_snapshot)?;
+        let val = bincode::deserialize::<FieldWrap<Fr>>(&ser_val)?;
+        Ok(KeyShare(val.into_inner()))
+    }
+
+    /// Returns our decryption share of a `LabelledCiphertext`, with a proof that it matches our
+    /// public key share.
+    ///
+    /// Fails with `LabelMismatch` unless the ciphertext carries the expected label, and with
+    /// `InvalidCiphertext` unless its proof binds it to that label.
+    pub fn decrypt_share_labelled<R: Rng>(
+        &self,
+        ct: &LabelledCiphertext,
+        label: &[u8],
+        rng: &mut R,
+    ) -> Result<LabelledDecryptionShare, Error> {
+        if ct.label != label {
+            return Err(Error::LabelMismatch);
+        }
+        let u = ct.checked_u().ok_or(Error::InvalidCiphertext)?;
+        let mut point = u;
+        point.mul_assign(self.0);
+        let pub_key_share = G1Affine::one().mul(self.0);
+
+        // A Chaum-Pedersen proof that `point` is `u` multiplied by the discrete logarithm of
+        // our public key share.
+        let nonce = random_fr(rng);
+        let commit_g = G1Affine::one().mul(nonce);
+        let mut commit_u = u;
+        commit_u.mul_assign(nonce);
+        let challenge = share_challenge(&ct.u, &pub_key_share, &point, &commit_g, &commit_u);
+        let mut response = challenge;
+        response.mul_assign(&self.0);
+        response.add_assign(&nonce);
+        let proof = bincode::serialize(&(FieldWrap(challenge), FieldWrap(response)))?;
+        Ok(LabelledDecryptionShare {
+            point: g1_to_bytes(&point),
+            proof,
+        })
+    }
+
+    /// Returns our decryption share of the ciphertext, encrypted to the recipient's public key.
+    /// Only the recipient can combine the shares with `decrypt_with_encrypted_shares`, so whoever
+    /// relays them doesn't learn the message.
+    pub fn decrypt_share_for<PK: PublicKey, R: Rng>(
+        &self,
+        ct: &Ciphertext,
+        recipient: &PK,
+        rng: &mut R,
+    ) -> Result<Vec<u8>, Error> {
+        let dec_share = self
+            .secret_key_share()
+            .decrypt_share(ct)
+            .ok_or(Error::InvalidCiphertext)?;
+        let ser_share = bincode::serialize(&dec_share)?;
+        recipient.encrypt(ser_share, rng).map_err(Error::encrypt)
+    }
+}
+
+/// A message encrypted to a threshold key together with a public label, e.g. a tenant, purpose or
+/// record ID, in the style of TDH2.
+///
+/// The ciphertext contains a proof of knowledge of its randomness that is bound to the label. The
+/// nodes check it before issuing a decryption share, so the ciphertext can't be decrypted under
+/// a different label, nor modified to carry one: doing so requires a new proof, and thus the
+/// randomness, i.e. the plaintext.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct LabelledCiphertext {
+    label: Vec<u8>,
+    /// The compressed point `r * G`.
+    u: Vec<u8>,
+    /// The message, xored with a key stream derived from `r` times the master public key.
+    v: Vec<u8>,
+    /// The serialized challenge and response of the proof of knowledge of `r`.
+    proof: Vec<u8>,
+}
+
+impl LabelledCiphertext {
+    /// Encrypts the message to the master public key of the key set, under the given label.
+    pub fn encrypt<M: AsRef<[u8]>, R: Rng>(
+        pub_key_set: &PublicKeySet,
+        msg: M,
+        label: &[u8],
+        rng: &mut R,
+    ) -> Result<LabelledCiphertext, Error> {
+        let r = random_fr(rng);
+        let mut shared = pub_key_set_commitment(pub_key_set)?.evaluate(0);
+        shared.mul_assign(r);
+        let u = g1_to_bytes(&G1Affine::one().mul(r));
+        let v = xor_with_key_stream(&shared, msg.as_ref());
+
+        // A Schnorr proof of knowledge of `r`, bound to the ciphertext and the label.
+        let nonce = random_fr(rng);
+        let commit = G1Affine::one().mul(nonce);
+        let challenge = ciphertext_challenge(&u, &v, label, &commit);
+        let mut response = challenge;
+        response.mul_assign(&r);
+        response.add_assign(&nonce);
+        let proof = bincode::serialize(&(FieldWrap(challenge), FieldWrap(response)))?;
+        Ok(LabelledCiphertext {
+            label: label.to_vec(),
+            u,
+            v,
+            proof,
+        })
+    }
+
+    /// Returns the label the ciphertext is bound to.
+    pub fn label(&self) -> &[u8] {
+        &self.label
+    }
+
+    /// Returns `true` if the ciphertext is well formed and its proof matches its label.
+    pub fn verify(&self) -> bool {
+        self.checked_u().is_some()
+    }
+
+    /// Returns `true` if the decryption share was issued by the node with the given share index,
+    /// i.e. its position in the committee.
+    pub fn verify_decryption_share(
+        &self,
+        pub_key_set: &PublicKeySet,
+        idx: usize,
+        share: &LabelledDecryptionShare,
+    ) -> bool {
+        let opt_points = g1_from_bytes(&self.u).zip(g1_from_bytes(&share.point));
+        let opt_proof = bincode::deserialize::<(FieldWrap<Fr>, FieldWrap<Fr>)>(&share.proof).ok();
+        let commit = pub_key_set_commitment(pub_key_set).ok();
+        let ((u, point), (challenge, response), commit) = match (opt_points, opt_proof, commit) {
+            (Some(points), Some(proof), Some(commit)) => (points, proof, commit),
+            _ => return false,
+        };
+        let (challenge, response) = (challenge.into_inner(), response.into_inner());
+        let pub_key_share = commit.evaluate(idx as u64 + 1);
+        let commit_g = schnorr_commitment(
+            G1Affine::one().into_projective(),
+            pub_key_share,
+            challenge,
+            response,
+        );
+        let commit_u = schnorr_commitment(u, point, challenge, response);
+        challenge == share_challenge(&self.u, &pub_key_share, &point, &commit_g, &commit_u)
+    }
+
+    /// Combines more than `threshold` decryption shares, by share index, and returns the
+    /// message. The shares must have been checked with `verify_decryption_share`.
+    pub fn decrypt<'a, I>(&self, pub_key_set: &PublicKeySet, shares: I) -> Result<Vec<u8>, Error>
+    where
+        I: IntoIterator<Item = (usize, &'a LabelledDecryptionShare)>,
+    {
+        let mut points = BTreeMap::new();
+        for (idx, share) in shares.into_iter().take(pub_key_set.threshold() + 1) {
+            let point = g1_from_bytes(&share.point).ok_or(Error::InvalidDecryptionShare(idx))?;
+            points.insert(idx as u64, point);
+        }
+        if points.len() <= pub_key_set.threshold() {
+            return Err(Error::DecryptionShareCount(points.len()));
+        }
+        let indices: Vec<u64> = points.keys().cloned().collect();
+        let mut shared = G1::zero();
+        for (idx, mut point) in points {
+            point.mul_assign(lagrange_coefficient(idx, &indices));
+            shared.add_assign(&point);
+        }
+        Ok(xor_with_key_stream(&shared, &self.v))
+    }
+
+    /// Returns the point `u`, if the ciphertext's proof is valid.
+    fn checked_u(&self) -> Option<G1> {
+        let u = g1_from_bytes(&self.u)?;
+        let (challenge, response) =
+            bincode::deserialize::<(FieldWrap<Fr>, FieldWrap<Fr>)>(&self.proof).ok()?;
+        let (challenge, response) = (challenge.into_inner(), response.into_inner());
+        let commit = schnorr_commitment(G1Affine::one().into_projective(), u, challenge, response);
+        if challenge != ciphertext_challenge(&self.u, &self.v, &self.label, &commit) {
+            return None;
+        }
+        Some(u)
+    }
+}
+
+/// A node's decryption share of a `LabelledCiphertext`, with a proof that it was computed with
+/// the key share matching the node's public key share.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct LabelledDecryptionShare {
+    /// The compressed point `x_i * u`, where `x_i` is the node's key share.
+    point: Vec<u8>,
+    /// The serialized challenge and response of the proof.
+    proof: Vec<u8>,
+}
+
+/// Decrypts the decryption shares of `ct` that were encrypted to us with
+/// `KeyShare::decrypt_share_for`, by share index, and combines the valid ones. Returns the
+/// message and the indices of the shares that can't be decrypted or don't match their public key
+/// share.
+pub fn decrypt_with_encrypted_shares<SK: SecretKey>(
+    pub_key_set: &PublicKeySet,
+    ct: &Ciphertext,
+    enc_shares: &BTreeMap<usize, Vec<u8>>,
+    sec_key: &SK,
+) -> Result<(Vec<u8>, Vec<usize>), Error> {
+    if !ct.verify() {
+        return Err(Error::InvalidCiphertext);
+    }
+    let mut dec_shares = BTreeMap::new();
+    let mut invalid = Vec::new();
+    for (idx, enc_share) in enc_shares {
+        let dec_share = sec_key
+            .decrypt(enc_share)
+            .ok()
+            .and_then(|ser_share| bincode::deserialize::<DecryptionShare>(&ser_share).ok())
+            .filter(|share| {
+                let pub_key_share = pub_key_set.public_key_share(*idx);
+                pub_key_share.verify_decryption_share(share, ct)
+            });
+        match dec_share {
+            Some(dec_share) => {
+                dec_shares.insert(*idx, dec_share);
+            }
+            None => invalid.push(*idx),
+        }
+    }
+    if dec_shares.len() <= pub_key_set.threshold() {
+        return Err(Error::DecryptionShareCount(dec_shares.len()));
+    }
+    let msg = pub_key_set
+        .decrypt(&dec_shares, ct)
+        .map_err(|_| Error::DecryptionShareCount(dec_shares.len()))?;
+    Ok((msg, invalid))
+}
+
+/// The prefix of every request a recipient signs for `decrypt_for_request`.
+const DECRYPT_FOR_DOMAIN: &[u8] = b"TED-DECRYPT-FOR-V1";
+
+/// Returns the bytes a recipient signs with its secret key to request the decryption shares of
+/// `ct` with the key of the given session, encrypted to `recipient`. Nodes only issue such shares
+/// for a signed request, so whoever relays it can't swap in another recipient.
+pub fn decrypt_for_request(
+    session_id: u64,
+    ct: &Ciphertext,
+    recipient: &threshold_crypto::PublicKey,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = DECRYPT_FOR_DOMAIN.to_vec();
+    bytes.extend(bincode::serialize(&(session_id, ct, recipient))?);
+    Ok(bytes)
+}
+
+/// The prefix of the confirmation signed when a key generation is finalized.
+const DKG_CONFIRM_DOMAIN: &[u8] = b"TED-DKG-CONFIRM-V1";
+
+/// Returns the confirmation the members sign in the `DkgConfirm` context when they finalize a
+/// session: the session ID as a big-endian `u64` and the SHA-256 hash of the public key set. The
+/// message is fixed by the session, so a finalize request can't make the members sign anything
+/// else with their new key shares.
+pub fn dkg_confirmation(session_id: u64, pub_key_set: &PublicKeySet) -> Result<Vec<u8>, Error> {
+    let mut bytes = DKG_CONFIRM_DOMAIN.to_vec();
+    bytes.extend_from_slice(&session_id.to_be_bytes());
+    bytes.extend_from_slice(&Sha256::digest(bincode::serialize(pub_key_set)?));
+    Ok(bytes)
+}
+
+/// The prefix of every message signed in a `SigningContext`.
+const SIGNING_DOMAIN: &[u8] = b"TED-SIGN-V1";
+
+/// A registered purpose of signatures. The context is bound into the signed bytes, so a
+/// signature made in one context is not valid in any other.
+#[derive(Deserialize, Serialize, Clone, Copy, Hash, Eq, PartialEq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigningContext {
+    /// The confirmation that a key generation succeeded, signed with the new key shares.
+    DkgConfirm,
+    /// An attestation of arbitrary data by the committee.
+    Attestation,
+    /// A round of the randomness beacon.
+    Beacon,
+}
+
+impl SigningContext {
+    /// Returns the context's domain tag.
+    pub fn tag(self) -> &'static str {
+        match self {
+            SigningContext::DkgConfirm => "dkg-confirm",
+            SigningContext::Attestation => "attestation",
+            SigningContext::Beacon => "beacon",
+        }
+    }
+
+    /// Returns `true` if the nodes sign any message in this context on request. The other
+    /// contexts are reserved for the messages of their protocol.
+    pub fn is_open(self) -> bool {
+        self == SigningContext::Attestation
+    }
+
+    /// Returns the bytes that are signed for the message: the signing domain, the length of the
+    /// tag as a big-endian `u32`, the tag and the message.
+    pub fn signed_bytes(self, msg: &[u8]) -> Vec<u8> {
+        let tag = self.tag().as_bytes();
+        let mut bytes = Vec::with_capacity(SIGNING_DOMAIN.len() + 4 + tag.len() + msg.len());
+        bytes.extend_from_slice(SIGNING_DOMAIN);
+        bytes.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(tag);
+        bytes.extend_from_slice(msg);
+        bytes
+    }
+
+    /// Returns `true` if the signature of the message in this context matches the public key.
+    pub fn verify(self, pk: &threshold_crypto::PublicKey, sig: &Signature, msg: &[u8]) -> bool {
+        pk.verify(sig, self.signed_bytes(msg))
+    }
+
+    /// Returns `true` if the signature share of the message in this context matches the public
+    /// key share.
+    pub fn verify_share(
+        self,
+        pk_share: &PublicKeyShare,
+        share: &SignatureShare,
+        msg: &[u8],
+    ) -> bool {
+        pk_share.verify(share, self.signed_bytes(msg))
+    }
+}
+
+/// A round of the randomness beacon.
+///
+/// In round `r`, the committee signs `r` and the signature of round `r - 1` in the `Beacon`
+/// context. BLS signatures are unique, so no subset of the members can choose the signature, and
+/// the round's randomness, its hash, is unbiased. Each round can be verified with only the master
+/// public key.
+#[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct BeaconRound {
+    /// The round number, starting at 0.
+    pub round: u64,
+    /// The signature of the previous round, or `None` in round 0.
+    pub prev_signature: Option<Signature>,
+    pub signature: Signature,
+    /// The SHA-256 hash of the signature.
+    pub randomness: [u8; 32],
+}
+
+impl BeaconRound {
+    /// Returns the round with the given signature, and its randomness.
+    pub fn new(round: u64, prev_signature: Option<Signature>, signature: Signature) -> Self {
+        let randomness = Sha256::digest(signature.to_bytes()).into();
+        BeaconRound {
+            round,
+            prev_signature,
+            signature,
+            randomness,
+        }
+    }
+
+    /// Returns the message signed in the round: the round number as a big-endian `u64`, followed
+    /// by the previous signature, if any.
+    pub fn message(round: u64, prev_signature: Option<&Signature>) -> Vec<u8> {
+        let mut msg = round.to_be_bytes().to_vec();
+        if let Some(prev_signature) = prev_signature {
+            msg.extend_from_slice(&prev_signature.to_bytes());
+        }
+        msg
+    }
+
+    /// Returns `true` if the signature matches the master public key and the randomness matches
+    /// the signature. Only round 0 has no previous signature.
+    pub fn verify(&self, pk: &threshold_crypto::PublicKey) -> bool {
+        let msg = BeaconRound::message(self.round, self.prev_signature.as_ref());
+        (self.round == 0) == self.prev_signature.is_none()
+            && SigningContext::Beacon.verify(pk, &self.signature, &msg)
+            && self.randomness == <[u8; 32]>::from(Sha256::digest(self.signature.to_bytes()))
+    }
+
+    /// Returns `true` if this round directly follows `prev`.
+    pub fn follows(&self, prev: &BeaconRound) -> bool {
+        self.round == prev.round + 1 && self.prev_signature.as_ref() == Some(&prev.signature)
+    }
+}
+
+/// The times of the beacon rounds: round `r` is due at `genesis + r * period`, in seconds since
+/// the Unix epoch. Members refuse to sign a round before it is due, so nobody can learn the
+/// randomness of a future round ahead of time.
+#[derive(Deserialize, Serialize, Clone, Copy, Hash, Eq, PartialEq, Debug)]
+pub struct BeaconSchedule {
+    /// The time of round 0, in seconds since the Unix epoch.
+    pub genesis: u64,
+    /// The time between two rounds, in seconds.
+    pub period: u64,
+}
+
+impl BeaconSchedule {
+    /// Returns the time the round is due, or `None` if it is out of range.
+    pub fn round_time(&self, round: u64) -> Option<u64> {
+        round
+            .checked_mul(self.period)
+            .and_then(|offset| offset.checked_add(self.genesis))
+    }
+
+    /// Returns `true` if the round is due at the given time.
+    pub fn is_due(&self, round: u64, now: u64) -> bool {
+        self.round_time(round).map_or(false, |time| time <= now)
+    }
+}
+
+/// Verifies consecutive beacon rounds against the master public key. The first one need not be
+/// round 0: each round carries the previous signature.
+pub fn verify_beacon_chain(
+    pk: &threshold_crypto::PublicKey,
+    rounds: &[BeaconRound],
+) -> Result<(), Error> {
+    let mut prev: Option<&BeaconRound> = None;
+    for round in rounds {
+        if !round.verify(pk) || prev.map_or(false, |prev| !round.follows(prev)) {
+            return Err(Error::InvalidBeaconRound(round.round));
+        }
+        prev = Some(round);
+    }
+    Ok(())
+}
+
+/// The current format version of a `KeyGenSnapshot`.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// A serializable snapshot of the state of a `SyncKeyGen` or `AsyncKeyGen`, to resume the key
+/// generation after a restart.
+///
+/// Our secret values, i.e. the rows of our part, the values we verified and the key share we
+/// refresh, are encrypted to our own public key. Our secret key itself is not included: it must
+/// be passed to `restore` again.
+#[derive(Deserialize, Serialize)]
+pub struct KeyGenSnapshot<N: Ord, PK = threshold_crypto::PublicKey> {
+    /// The format version, `SNAPSHOT_VERSION` when the snapshot was taken.
+    version: u32,
+    our_id: N,
+    pub_keys: PubKeyMap<N, PK>,
+    threshold: usize,
+    /// The commitment and the nodes which have acked, of every handled part.
+    parts: BTreeMap<u64, (BivarCommitment, BTreeSet<u64>)>,
+    disqualified: BTreeSet<u64>,
+    refresh: Option<RefreshSnapshot<N>>,
+    /// The `Ack`s buffered by an `AsyncKeyGen`.
+    pending_acks: BTreeMap<u64, Vec<(N, Ack)>>,
+    transcript: Transcript,
+    /// The serialized `SnapshotSecrets`, encrypted to our own public key.
+    secrets: Vec<u8>,
+}
+
+impl<N: Ord + DeserializeOwned, PK: DeserializeOwned> KeyGenSnapshot<N, PK> {
+    /// Deserializes a snapshot from the start of `bytes`, and advances `bytes` past it.
+    ///
+    /// The format version is read first, so that a snapshot in a format we can't read fails with
+    /// `SnapshotVersion` instead of a deserialization error. Version 1 snapshots were taken before
+    /// we recorded transcripts: they are migrated with an empty transcript.
+    pub fn read(bytes: &mut &[u8]) -> Result<Self, Error> {
+        let version: u32 = bincode::deserialize(bytes).map_err(Error::invalid_snapshot)?;
+        match version {
+            1 => {
+                let snapshot: KeyGenSnapshotV1<N, PK> =
+                    bincode::deserialize_from(bytes).map_err(Error::invalid_snapshot)?;
+                Ok(snapshot.into())
+            }
+            SNAPSHOT_VERSION => bincode::deserialize_from(bytes).map_err(Error::invalid_snapshot),
+            _ => Err(Error::SnapshotVersion(version)),
+        }
+    }
+}
+
+/// A `KeyGenSnapshot` in format version 1, without the transcript.
+#[derive(Deserialize)]
+struct KeyGenSnapshotV1<N: Ord, PK> {
+    version: u32,
+    our_id: N,
+    pub_keys: PubKeyMap<N, PK>,
+    threshold: usize,
+    parts: BTreeMap<u64, (BivarCommitment, BTreeSet<u64>)>,
+    disqualified: BTreeSet<u64>,
+    refresh: Option<RefreshSnapshot<N>>,
+    pending_acks: BTreeMap<u64, Vec<(N, Ack)>>,
+    secrets: Vec<u8>,
+}
+
+impl<N: Ord, PK> From<KeyGenSnapshotV1<N, PK>> for KeyGenSnapshot<N, PK> {
+    fn from(snapshot: KeyGenSnapshotV1<N, PK>) -> Self {
+        KeyGenSnapshot {
+            version: SNAPSHOT_VERSION,
+            our_id: snapshot.our_id,
+            pub_keys: snapshot.pub_keys,
+            threshold: snapshot.threshold,
+            parts: snapshot.parts,
+            disqualified: snapshot.disqualified,
+            refresh: snapshot.refresh,
+            pending_acks: snapshot.pending_acks,
+            transcript: Transcript::default(),
+            secrets: snapshot.secrets,
+        }
+    }
+}
+
+impl<N: Ord + Debug, PK> Debug for KeyGenSnapshot<N, PK> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyGenSnapshot")
+            .field("version", &self.version)
+            .field("our_id", &self.our_id)
+            .field("parts", &format!("<{} parts>", self.parts.len()))
+            .field("secrets", &"<encrypted>")
+            .finish()
+    }
+}
+
+/// The public part of a `RefreshState` in a `KeyGenSnapshot`.
+#[derive(Deserialize, Serialize)]
+struct RefreshSnapshot<N> {
+    commit: Commitment,
+    offsets: BTreeMap<u64, FieldWrap<Fr>>,
+    dealers: Option<Vec<N>>,
+}
+
+/// The secret part of a `KeyGenSnapshot`.
+#[derive(Deserialize, Serialize)]
+struct SnapshotSecrets {
+    /// The rows of our own bivariate polynomial.
+    our_rows: Vec<Poly>,
+    /// The verified values of every part, by proposer index.
+    values: BTreeMap<u64, BTreeMap<u64, FieldWrap<Fr>>>,
+    /// In a share refresh, our current key share.
+    key_share: Option<FieldWrap<Fr>>,
+}
+
+/// The key whose shares a `SyncKeyGen` refreshes or reshares.
+#[derive(Debug)]
+struct RefreshState<N> {
+    /// The commitment of the key's public key set.
+    commit: Commitment,
+    /// In a share refresh, our current key share, unless we are an observer.
+    key_share: Option<KeyShare>,
+    /// The offsets of the handled parts, by proposer index.
+    offsets: BTreeMap<u64, Fr>,
+    /// In a resharing, the IDs of the current share holders, who are the proposers.
+    dealers: Option<Vec<N>>,
+}
+
 /// The information needed to track a single proposer's secret sharing process.
 #[derive(Debug, PartialEq, Eq)]
 struct ProposalState {
@@ -170,6 +909,12 @@ impl ProposalState {
     fn is_complete(&self, threshold: usize) -> bool {
         self.acks.len() > threshold
     }
+
+    /// Returns `true` if we verified at least `threshold + 1` values of our row, so that we can
+    /// interpolate our share of this part.
+    fn has_values(&self, threshold: usize) -> bool {
+        self.values.len() > threshold
+    }
 }
 
 /// The outcome of handling and verifying a `Part` message.
@@ -190,9 +935,20 @@ pub enum AckOutcome {
     Invalid(AckFault),
 }
 
+/// The outcome of judging a `Complaint`.
+pub enum ComplaintOutcome {
+    /// The proposer didn't reveal a row matching its commitment: it is faulty, and its part is
+    /// discarded.
+    Upheld,
+    /// The proposer revealed a valid row, or the complaint is not about the part we handled. If we
+    /// are the accuser, this contains the `Ack` that we now multicast for the part.
+    Dismissed(Option<Ack>),
+}
+
 /// A synchronous algorithm for dealerless distributed key generation.
 ///
-/// It requires that all nodes handle all messages in the exact same order.
+/// It requires that all nodes handle all messages in the exact same order. If that can't be
+/// guaranteed, use `AsyncKeyGen` instead.
 #[derive(Debug)]
 pub struct SyncKeyGen<N, PK: PublicKey = threshold_crypto::PublicKey> {
     /// Our node ID.
@@ -205,6 +961,14 @@ pub struct SyncKeyGen<N, PK: PublicKey = threshold_crypto::PublicKey> {
     pub_keys: PubKeyMap<N, PK>,
     /// Proposed bivariate polynomials.
     parts: BTreeMap<u64, ProposalState>,
+    /// Proposers whose part was discarded after a complaint.
+    disqualified: BTreeSet<u64>,
+    /// The rows of our own bivariate polynomial, by node index, kept to answer complaints.
+    our_rows: Vec<Poly>,
+    /// The key to refresh or reshare, if this is not a new key.
+    refresh: Option<RefreshState<N>>,
+    /// The messages we handled so far.
+    transcript: Transcript,
     /// The degree of the generated polynomial.
     threshold: usize,
 }
@@ -222,35 +986,174 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         threshold: usize,
         rng: &mut R,
     ) -> Result<(Self, Option<Part>), Error> {
+        let mut key_gen = SyncKeyGen::create(our_id, sec_key, pub_keys, threshold);
+        if key_gen.our_idx.is_none() {
+            return Ok((key_gen, None)); // No part: we are an observer.
+        }
+
+        let our_part = BivarPoly::random(threshold, rng);
+        let part = key_gen.deal(&our_part, rng)?;
+        Ok((key_gen, Some(part)))
+    }
+
+    /// Creates a new `SyncKeyGen` instance that refreshes the shares of an existing key, together
+    /// with the `RefreshPart` message that should be multicast to all nodes.
+    ///
+    /// The nodes must be the ones holding the key shares, in the same order. Each of them deals a
+    /// bivariate polynomial with a zero constant term, so `generate` returns re-randomized shares
+    /// of the same master key: `public_key()` doesn't change, ciphertexts encrypted to it can
+    /// still be decrypted, but shares from before the refresh don't combine with shares from
+    /// after it. Unless we are an observer, we need our current `key_share`.
+    pub fn refresh<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        pub_key_set: &PublicKeySet,
+        key_share: Option<&KeyShare>,
+        rng: &mut R,
+    ) -> Result<(Self, Option<RefreshPart>), Error> {
+        let threshold = pub_key_set.threshold();
+        if threshold >= pub_keys.len() {
+            return Err(Error::InvalidThreshold(threshold, pub_keys.len()));
+        }
+        let mut key_gen = SyncKeyGen::create(our_id, sec_key, pub_keys, threshold);
+        let key_share = match (key_gen.our_idx, key_share) {
+            (None, _) => None,
+            (Some(_), Some(key_share)) => Some(key_share.clone()),
+            (Some(_), None) => return Err(Error::MissingKeyShare),
+        };
+        key_gen.refresh = Some(RefreshState {
+            commit: pub_key_set_commitment(pub_key_set)?,
+            key_share,
+            offsets: BTreeMap::new(),
+            dealers: None,
+        });
+        if key_gen.our_idx.is_none() {
+            return Ok((key_gen, None)); // No part: we are an observer.
+        }
+
+        // Revealing the constant term is safe: it is independent of all other coefficients.
+        let our_part = BivarPoly::random(threshold, rng);
+        let offset = bincode::serialize(&FieldWrap(our_part.evaluate(0, 0)))?;
+        let part = key_gen.deal(&our_part, rng)?;
+        Ok((key_gen, Some(RefreshPart(part, offset))))
+    }
+
+    /// Creates a new `SyncKeyGen` instance that reshares an existing key to the nodes in
+    /// `pub_keys`, with a possibly different `threshold`, together with the `RefreshPart` message
+    /// that should be multicast to all nodes.
+    ///
+    /// The current share holders are the nodes in `old_pub_keys`. Each of them deals a bivariate
+    /// polynomial whose constant term is its share of `pub_key_set`, and the new shares combine
+    /// at least `pub_key_set.threshold() + 1` of these parts. The new key has the same
+    /// `public_key()`, so existing ciphertexts and signatures keep working.
+    ///
+    /// Only the current share holders produce a part, and they need their `key_share`. Unless
+    /// they are also in `pub_keys`, they are observers of the new key, and only need to answer
+    /// complaints.
+    #[allow(clippy::too_many_arguments)]
+    pub fn reshare<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        threshold: usize,
+        old_pub_keys: &PubKeyMap<N, PK>,
+        pub_key_set: &PublicKeySet,
+        key_share: Option<&KeyShare>,
+        rng: &mut R,
+    ) -> Result<(Self, Option<RefreshPart>), Error> {
+        if threshold >= pub_keys.len() {
+            return Err(Error::InvalidThreshold(threshold, pub_keys.len()));
+        }
+        if pub_key_set.threshold() >= old_pub_keys.len() {
+            let old_threshold = pub_key_set.threshold();
+            return Err(Error::InvalidThreshold(old_threshold, old_pub_keys.len()));
+        }
+        let dealers: Vec<N> = old_pub_keys.keys().cloned().collect();
+        let is_dealer = dealers.contains(&our_id);
+        let mut key_gen = SyncKeyGen::create(our_id, sec_key, pub_keys, threshold);
+        key_gen.refresh = Some(RefreshState {
+            commit: pub_key_set_commitment(pub_key_set)?,
+            key_share: None,
+            offsets: BTreeMap::new(),
+            dealers: Some(dealers),
+        });
+        if !is_dealer {
+            return Ok((key_gen, None)); // No part: we don't hold a share of the key.
+        }
+        let key_share = key_share.ok_or(Error::MissingKeyShare)?;
+
+        // The offset is the difference between the random constant term and our key share, so
+        // revealing it is safe.
+        let our_part = BivarPoly::random(threshold, rng);
+        let mut offset = our_part.evaluate(0, 0);
+        offset.sub_assign(&key_share.0);
+        let ser_offset = bincode::serialize(&FieldWrap(offset))?;
+        let part = key_gen.deal(&our_part, rng)?;
+        Ok((key_gen, Some(RefreshPart(part, ser_offset))))
+    }
+
+    /// Creates a `SyncKeyGen` instance that hasn't dealt a part yet.
+    fn create(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        threshold: usize,
+    ) -> Self {
         let our_idx = pub_keys
             .keys()
             .position(|id| *id == our_id)
             .map(|idx| idx as u64);
-        let key_gen = SyncKeyGen {
+        SyncKeyGen {
             our_id,
             our_idx,
             sec_key,
             pub_keys,
             parts: BTreeMap::new(),
+            disqualified: BTreeSet::new(),
+            our_rows: Vec::new(),
+            refresh: None,
+            transcript: Transcript::default(),
             threshold,
-        };
-        if our_idx.is_none() {
-            return Ok((key_gen, None)); // No part: we are an observer.
         }
+    }
 
-        let our_part = BivarPoly::random(threshold, rng);
+    /// Encrypts the rows of our bivariate polynomial to their nodes, and returns our `Part`.
+    fn deal<R: rand::Rng>(&mut self, our_part: &BivarPoly, rng: &mut R) -> Result<Part, Error> {
         let commit = our_part.commitment();
-        let encrypt = |(i, pk): (usize, &PK)| {
-            let row = bincode::serialize(&our_part.row(i + 1))?;
-            Ok(pk.encrypt(&row, rng).map_err(Error::encrypt)?)
+        let our_rows: Vec<Poly> = (0..self.pub_keys.len())
+            .map(|i| our_part.row(i + 1))
+            .collect();
+        let encrypt = |(row, pk): (&Poly, &PK)| {
+            let ser_row = bincode::serialize(row)?;
+            Ok(pk.encrypt(&ser_row, rng).map_err(Error::encrypt)?)
         };
-        let rows = key_gen
-            .pub_keys
-            .values()
-            .enumerate()
+        let rows = our_rows
+            .iter()
+            .zip(self.pub_keys.values())
             .map(encrypt)
             .collect::<Result<Vec<Vec<u8>>, Error>>()?;
-        Ok((key_gen, Some(Part(commit, rows))))
+        self.our_rows = our_rows;
+        Ok(Part(commit, rows))
+    }
+
+    /// Creates a new `SyncKeyGen` instance for a `t`-of-`n` key, like `new`.
+    ///
+    /// Fails if `params` don't match the number of public keys, or if their quorum is smaller
+    /// than `min_quorum`.
+    pub fn with_params<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        params: ThresholdParams,
+        min_quorum: usize,
+        rng: &mut R,
+    ) -> Result<(Self, Option<Part>), Error> {
+        if params.num_nodes != pub_keys.len() {
+            return Err(Error::InvalidThreshold(params.threshold, pub_keys.len()));
+        }
+        params.validate(min_quorum)?;
+        SyncKeyGen::new(our_id, sec_key, pub_keys, params.threshold, rng)
     }
 
     /// Returns the id of this node.
@@ -263,6 +1166,17 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         &self.pub_keys
     }
 
+    /// Returns `true` if we are an observer: we are not in the map of participating nodes, and
+    /// don't receive a key share.
+    pub fn is_observer(&self) -> bool {
+        self.our_idx.is_none()
+    }
+
+    /// Returns the transcript of all `Part`s, `Ack`s and complaints we handled so far.
+    pub fn transcript(&self) -> &Transcript {
+        &self.transcript
+    }
+
     /// Handles a `Part` message. If it is valid, returns an `Ack` message to be broadcast.
     ///
     /// If we are only an observer, `None` is returned instead and no messages need to be sent.
@@ -275,20 +1189,141 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         part: Part,
         rng: &mut R,
     ) -> Result<PartOutcome, Error> {
-        let sender_idx = self.node_index(sender_id).ok_or(Error::UnknownSender)?;
+        let sender_idx = self.proposer_index(sender_id).ok_or(Error::UnknownSender)?;
+        self.transcript
+            .push(sender_idx, TranscriptMessage::Part(part.clone()))?;
+        self.handle_recorded_part(sender_idx, part, rng)
+    }
+
+    /// Handles a `Part` message that is already recorded in the transcript.
+    fn handle_recorded_part<R: rand::Rng>(
+        &mut self,
+        sender_idx: u64,
+        part: Part,
+        rng: &mut R,
+    ) -> Result<PartOutcome, Error> {
+        if let Some(refresh) = &self.refresh {
+            if !refresh.offsets.contains_key(&sender_idx) {
+                // Without its offset, the part would change the master key.
+                return Ok(PartOutcome::Invalid(PartFault::RefreshConstant));
+            }
+        }
         let row = match self.handle_part_or_fault(sender_idx, part) {
             Ok(Some(row)) => row,
             Ok(None) => return Ok(PartOutcome::Valid(None)),
             Err(fault) => return Ok(PartOutcome::Invalid(fault)),
         };
         // The row is valid. Encrypt one value for each node and broadcast an `Ack`.
-        let mut values = Vec::new();
-        for (idx, pk) in self.pub_keys.values().enumerate() {
-            let val = row.evaluate(idx + 1);
-            let ser_val = bincode::serialize(&FieldWrap(val))?;
-            values.push(pk.encrypt(ser_val, rng).map_err(Error::encrypt)?);
+        let ack = self.ack_row(sender_idx, &row, rng)?;
+        Ok(PartOutcome::Valid(Some(ack)))
+    }
+
+    /// Handles a `RefreshPart` message of a share refresh or resharing, like `handle_part`.
+    ///
+    /// Fails with `NotRefreshing` if this instance was not created with `refresh` or `reshare`.
+    pub fn handle_refresh_part<R: rand::Rng>(
+        &mut self,
+        sender_id: &N,
+        RefreshPart(part, ser_offset): RefreshPart,
+        rng: &mut R,
+    ) -> Result<PartOutcome, Error> {
+        let sender_idx = self.proposer_index(sender_id).ok_or(Error::UnknownSender)?;
+        let refresh = self.refresh.as_mut().ok_or(Error::NotRefreshing)?;
+        // Like in `handle_part`, the part is recorded even if it turns out to be invalid.
+        self.transcript
+            .push(sender_idx, TranscriptMessage::Part(part.clone()))?;
+        let offset = match bincode::deserialize::<FieldWrap<Fr>>(&ser_offset) {
+            Ok(offset) => offset.into_inner(),
+            Err(_) => return Ok(PartOutcome::Invalid(PartFault::RefreshConstant)),
+        };
+        // Without the offset, the constant term must be zero in a share refresh, and the
+        // dealer's key share in a resharing.
+        let mut expected = G1Affine::one().mul(offset);
+        if refresh.dealers.is_some() {
+            expected.add_assign(&refresh.commit.evaluate(sender_idx + 1));
+        }
+        if part.0.evaluate(0, 0) != expected {
+            return Ok(PartOutcome::Invalid(PartFault::RefreshConstant));
+        }
+        // A different part from the same sender is detected by `handle_part_or_fault`.
+        refresh.offsets.entry(sender_idx).or_insert(offset);
+        self.handle_recorded_part(sender_idx, part, rng)
+    }
+
+    /// Returns a `Complaint` against the sender of a `Part` for which `handle_part` returned
+    /// `PartOutcome::Invalid`. It must be multicast instead of an `Ack`.
+    pub fn complain(&self, sender_id: &N, part: Part) -> Result<Complaint, Error> {
+        let sender_idx = self.proposer_index(sender_id).ok_or(Error::UnknownSender)?;
+        Ok(Complaint(sender_idx, part))
+    }
+
+    /// Returns our `Justification` in answer to a `Complaint` by the given node, revealing that
+    /// node's row of our part.
+    ///
+    /// If we are only an observer, we have no part and `None` is returned instead.
+    pub fn justify(&self, accuser_id: &N) -> Result<Option<Justification>, Error> {
+        let accuser_idx = self.node_index(accuser_id).ok_or(Error::UnknownSender)?;
+        Ok(self
+            .our_rows
+            .get(accuser_idx as usize)
+            .map(|row| Justification(accuser_idx, row.clone())))
+    }
+
+    /// Judges a `Complaint`, given the accused proposer's `Justification`, or `None` if it didn't
+    /// answer. If the complaint is upheld, the proposer's part is discarded: it doesn't count
+    /// towards the generated key, and `Ack`s for it are ignored.
+    ///
+    /// All participating nodes must judge the exact same sequence of complaints, after handling
+    /// all `Part`s and before handling the `Ack`s.
+    pub fn handle_complaint<R: rand::Rng>(
+        &mut self,
+        accuser_id: &N,
+        complaint: Complaint,
+        opt_justification: Option<Justification>,
+        rng: &mut R,
+    ) -> Result<ComplaintOutcome, Error> {
+        let accuser_idx = self.node_index(accuser_id).ok_or(Error::UnknownSender)?;
+        let message = TranscriptMessage::Complaint(complaint.clone(), opt_justification.clone());
+        self.transcript.push(accuser_idx, message)?;
+        let Complaint(proposer_idx, Part(commit, _)) = complaint;
+        let commit_row = match self.parts.get(&proposer_idx) {
+            // Nobody accepted a part from the proposer, so it is already excluded.
+            None => {
+                self.disqualified.insert(proposer_idx);
+                return Ok(ComplaintOutcome::Upheld);
+            }
+            Some(state) if state.commit != commit => {
+                return Ok(ComplaintOutcome::Dismissed(None)); // Not the part we all handled.
+            }
+            Some(state) => state.commit.row(accuser_idx + 1),
+        };
+        let is_valid = |Justification(idx, row): &Justification| {
+            *idx == accuser_idx && row.commitment() == commit_row
+        };
+        let row = match opt_justification {
+            Some(justification) if is_valid(&justification) => justification.1,
+            _ => {
+                self.parts.remove(&proposer_idx);
+                self.disqualified.insert(proposer_idx);
+                return Ok(ComplaintOutcome::Upheld);
+            }
+        };
+        if self.our_idx != Some(accuser_idx) {
+            return Ok(ComplaintOutcome::Dismissed(None));
         }
-        Ok(PartOutcome::Valid(Some(Ack(sender_idx, values))))
+        // We are the accuser, and now know our valid row: acknowledge the part after all.
+        let ack = self.ack_row(proposer_idx, &row, rng)?;
+        Ok(ComplaintOutcome::Dismissed(Some(ack)))
+    }
+
+    /// Returns the IDs of the proposers whose part was discarded after a complaint.
+    pub fn disqualified_nodes(&self) -> Vec<N> {
+        self.proposer_ids()
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| self.disqualified.contains(&(*idx as u64)))
+            .map(|(_, id)| id)
+            .collect()
     }
 
     /// Handles an `Ack` message.
@@ -297,12 +1332,30 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
     /// Note that `handle_ack` also needs to explicitly be called with this instance's own `Ack`s.
     pub fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<AckOutcome, Error> {
         let sender_idx = self.node_index(sender_id).ok_or(Error::UnknownSender)?;
+        self.transcript
+            .push(sender_idx, TranscriptMessage::Ack(ack.clone()))?;
         Ok(match self.handle_ack_or_fault(sender_idx, ack) {
             Ok(()) => AckOutcome::Valid,
             Err(fault) => AckOutcome::Invalid(fault),
         })
     }
 
+    /// Encrypts one value of a verified row for each node, and returns the `Ack` to broadcast.
+    fn ack_row<R: rand::Rng>(
+        &self,
+        proposer_idx: u64,
+        row: &Poly,
+        rng: &mut R,
+    ) -> Result<Ack, Error> {
+        let mut values = Vec::new();
+        for (idx, pk) in self.pub_keys.values().enumerate() {
+            let val = row.evaluate(idx + 1);
+            let ser_val = bincode::serialize(&FieldWrap(val))?;
+            values.push(pk.encrypt(ser_val, rng).map_err(Error::encrypt)?);
+        }
+        Ok(Ack(proposer_idx, values))
+    }
+
     /// Returns the index of the node, or `None` if it is unknown.
     fn node_index(&self, node_id: &N) -> Option<u64> {
         self.pub_keys
@@ -311,6 +1364,40 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
             .map(|idx| idx as u64)
     }
 
+    /// Returns the index of the proposer, or `None` if it is unknown. In a resharing, the
+    /// proposers are the current share holders instead of the participating nodes.
+    fn proposer_index(&self, proposer_id: &N) -> Option<u64> {
+        match self.refresh.as_ref().and_then(|r| r.dealers.as_ref()) {
+            Some(dealers) => dealers
+                .iter()
+                .position(|id| id == proposer_id)
+                .map(|idx| idx as u64),
+            None => self.node_index(proposer_id),
+        }
+    }
+
+    /// Returns the ID of the proposer with the given index, or `None` if there is none.
+    pub fn proposer_id(&self, proposer_idx: u64) -> Option<N> {
+        self.proposer_ids().into_iter().nth(proposer_idx as usize)
+    }
+
+    /// Returns the IDs of all proposers, by index.
+    fn proposer_ids(&self) -> Vec<N> {
+        match self.refresh.as_ref().and_then(|r| r.dealers.as_ref()) {
+            Some(dealers) => dealers.clone(),
+            None => self.pub_keys.keys().cloned().collect(),
+        }
+    }
+
+    /// Returns the number of complete parts needed to generate the key: `threshold + 1`, or in
+    /// a resharing, the `threshold + 1` of the current key.
+    pub fn min_parts(&self) -> usize {
+        match &self.refresh {
+            Some(refresh) if refresh.dealers.is_some() => refresh.commit.degree() + 1,
+            _ => self.threshold + 1,
+        }
+    }
+
     /// Returns the number of complete parts. If this is at least `threshold + 1`, the keys can
     /// be generated, but it is possible to wait for more to increase security.
     pub fn count_complete(&self) -> usize {
@@ -322,14 +1409,40 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
 
     /// Returns `true` if the part of the given node is complete.
     pub fn is_node_ready(&self, proposer_id: &N) -> bool {
-        self.node_index(proposer_id)
+        self.proposer_index(proposer_id)
             .and_then(|proposer_idx| self.parts.get(&proposer_idx))
             .map_or(false, |part| part.is_complete(self.threshold))
     }
 
     /// Returns `true` if enough parts are complete to safely generate the new key.
     pub fn is_ready(&self) -> bool {
-        self.count_complete() > self.threshold
+        self.count_complete() >= self.min_parts()
+    }
+
+    /// Returns the IDs of the proposers whose `Part` we haven't handled, e.g. because they
+    /// missed the deadline. Disqualified proposers are not included.
+    pub fn missing_parts(&self) -> Vec<N> {
+        self.proposer_ids()
+            .into_iter()
+            .enumerate()
+            .filter(|(idx, _)| {
+                let idx = *idx as u64;
+                !self.parts.contains_key(&idx) && !self.disqualified.contains(&idx)
+            })
+            .map(|(_, id)| id)
+            .collect()
+    }
+
+    /// Returns the IDs of the nodes from which we haven't handled any `Ack`, e.g. because they
+    /// missed the deadline.
+    pub fn missing_acks(&self) -> Vec<N> {
+        let has_acked = |idx: u64| self.parts.values().any(|part| part.acks.contains(&idx));
+        self.pub_keys
+            .keys()
+            .enumerate()
+            .filter(|(idx, _)| !has_acked(*idx as u64))
+            .map(|(_, id)| id.clone())
+            .collect()
     }
 
     /// Returns the new secret key share and the public key set.
@@ -342,23 +1455,85 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
     /// All participating nodes must have handled the exact same sequence of `Part` and `Ack`
     /// messages before calling this method. Otherwise their key shares will not match.
     pub fn generate(&self) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error> {
-        let mut pk_commit = Poly::zero().commitment();
-        let mut opt_sk_val = self.our_idx.map(|_| Fr::zero());
-        let is_complete = |part: &&ProposalState| part.is_complete(self.threshold);
-        for part in self.parts.values().filter(is_complete) {
-            pk_commit += part.commit.row(0);
-            if let Some(sk_val) = opt_sk_val.as_mut() {
-                let row = Poly::interpolate(part.values.iter().take(self.threshold + 1));
-                sk_val.add_assign(&row.evaluate(0));
-            }
-        }
+        let is_complete = |(_, part): &(&u64, &ProposalState)| part.is_complete(self.threshold);
+        Ok(self.generate_from(self.parts.iter().filter(is_complete)))
+    }
+
+    /// Returns the public key set and our secret key share that are the sum of the given parts.
+    fn generate_from<'a, I>(&self, parts: I) -> (PublicKeySet, Option<SecretKeyShare>)
+    where
+        I: IntoIterator<Item = (&'a u64, &'a ProposalState)>,
+    {
+        let (pk_commit, opt_sk_val) = self.sum_parts(parts);
         let opt_sk = if let Some(mut fr) = opt_sk_val {
             let sk = SecretKeyShare::from_mut(&mut fr);
             Some(sk)
         } else {
             None
         };
-        Ok((pk_commit.into(), opt_sk))
+        (pk_commit.into(), opt_sk)
+    }
+
+    /// Returns the commitment and our value of the sum of the given parts, by proposer index.
+    ///
+    /// In a share refresh, the parts' offsets are subtracted and the sum is added to the
+    /// refreshed key. In a resharing, the parts without their offsets are weighted with the
+    /// Lagrange coefficients of their dealers, so that their constant terms sum to the master key.
+    fn sum_parts<'a, I>(&self, parts: I) -> (Commitment, Option<Fr>)
+    where
+        I: IntoIterator<Item = (&'a u64, &'a ProposalState)>,
+    {
+        let parts: Vec<(&u64, &ProposalState)> = parts.into_iter().collect();
+        let proposer_indices: Vec<u64> = parts.iter().map(|(idx, _)| **idx).collect();
+        let is_resharing = self.refresh.as_ref().map_or(false, |r| r.dealers.is_some());
+        let mut pk_commit = Poly::zero().commitment();
+        let mut opt_sk_val = self.our_idx.map(|_| Fr::zero());
+        for (idx, part) in parts {
+            let mut commit = part.commit.row(0);
+            let mut opt_val = opt_sk_val.map(|_| {
+                let row = Poly::interpolate(part.values.iter().take(self.threshold + 1));
+                row.evaluate(0)
+            });
+            let offset = self.refresh.as_ref().and_then(|r| r.offsets.get(idx));
+            if let Some(offset) = offset {
+                let mut neg_offset = *offset;
+                neg_offset.negate();
+                commit += Poly::constant(neg_offset).commitment();
+                if let Some(val) = opt_val.as_mut() {
+                    val.sub_assign(offset);
+                }
+            }
+            if is_resharing {
+                let lagrange = lagrange_coefficient(*idx, &proposer_indices);
+                commit = mul_commitment(&commit, lagrange);
+                if let Some(val) = opt_val.as_mut() {
+                    val.mul_assign(&lagrange);
+                }
+            }
+            pk_commit += commit;
+            if let (Some(sk_val), Some(val)) = (opt_sk_val.as_mut(), opt_val) {
+                sk_val.add_assign(&val);
+            }
+        }
+        match &self.refresh {
+            Some(refresh) if !is_resharing => {
+                pk_commit += &refresh.commit;
+                if let (Some(sk_val), Some(KeyShare(old_val))) =
+                    (opt_sk_val.as_mut(), refresh.key_share.as_ref())
+                {
+                    sk_val.add_assign(old_val);
+                }
+            }
+            _ => (),
+        }
+        (pk_commit, opt_sk_val)
+    }
+
+    /// Returns `true` if the part is complete and, unless we are an observer, we know enough of
+    /// our values to compute our share of it.
+    fn is_part_usable(&self, part: &ProposalState) -> bool {
+        part.is_complete(self.threshold)
+            && (self.our_idx.is_none() || part.has_values(self.threshold))
     }
 
     /// Returns the number of nodes participating in the key generation.
@@ -366,6 +1541,130 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         self.pub_keys.len()
     }
 
+    /// Returns the `t`-of-`n` parameters of the key being generated.
+    pub fn params(&self) -> ThresholdParams {
+        ThresholdParams::new(self.threshold, self.num_nodes())
+    }
+
+    /// Returns a snapshot of our state, from which `restore` resumes the key generation, e.g.
+    /// after a restart.
+    ///
+    /// Our secret values are encrypted to `our_pub_key`, which must belong to our secret key.
+    pub fn snapshot<R: rand::Rng>(
+        &self,
+        our_pub_key: &PK,
+        rng: &mut R,
+    ) -> Result<KeyGenSnapshot<N, PK>, Error> {
+        let wrap_values = |part: &ProposalState| {
+            let values = part.values.iter().map(|(idx, val)| (*idx, FieldWrap(*val)));
+            values.collect()
+        };
+        let secrets = SnapshotSecrets {
+            our_rows: self.our_rows.clone(),
+            values: self
+                .parts
+                .iter()
+                .map(|(idx, part)| (*idx, wrap_values(part)))
+                .collect(),
+            key_share: self
+                .refresh
+                .as_ref()
+                .and_then(|refresh| refresh.key_share.as_ref())
+                .map(|key_share| FieldWrap(key_share.0)),
+        };
+        let ser_secrets = bincode::serialize(&secrets)?;
+        let refresh = self.refresh.as_ref().map(|refresh| RefreshSnapshot {
+            commit: refresh.commit.clone(),
+            offsets: refresh
+                .offsets
+                .iter()
+                .map(|(idx, offset)| (*idx, FieldWrap(*offset)))
+                .collect(),
+            dealers: refresh.dealers.clone(),
+        });
+        Ok(KeyGenSnapshot {
+            version: SNAPSHOT_VERSION,
+            our_id: self.our_id.clone(),
+            pub_keys: self.pub_keys.clone(),
+            threshold: self.threshold,
+            parts: self
+                .parts
+                .iter()
+                .map(|(idx, part)| (*idx, (part.commit.clone(), part.acks.clone())))
+                .collect(),
+            disqualified: self.disqualified.clone(),
+            refresh,
+            pending_acks: BTreeMap::new(),
+            transcript: self.transcript.clone(),
+            secrets: our_pub_key
+                .encrypt(ser_secrets, rng)
+                .map_err(Error::encrypt)?,
+        })
+    }
+
+    /// Restores an instance from a `snapshot` of its state, given our secret key.
+    ///
+    /// Fails with `SnapshotVersion` if the snapshot has an unsupported format, and with
+    /// `InvalidSnapshot` if its secret values can't be decrypted with `sec_key`, or if it was
+    /// taken from an `AsyncKeyGen` with buffered `Ack`s.
+    pub fn restore(snapshot: KeyGenSnapshot<N, PK>, sec_key: PK::SecretKey) -> Result<Self, Error> {
+        let (key_gen, pending_acks) = SyncKeyGen::restore_with_pending_acks(snapshot, sec_key)?;
+        if !pending_acks.is_empty() {
+            return Err(Error::InvalidSnapshot("buffered Acks".to_string()));
+        }
+        Ok(key_gen)
+    }
+
+    /// Restores an instance from a `snapshot`, and returns it with the buffered `Ack`s.
+    #[allow(clippy::type_complexity)]
+    fn restore_with_pending_acks(
+        snapshot: KeyGenSnapshot<N, PK>,
+        sec_key: PK::SecretKey,
+    ) -> Result<(Self, BTreeMap<u64, Vec<(N, Ack)>>), Error> {
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(Error::SnapshotVersion(snapshot.version));
+        }
+        let ser_secrets = sec_key
+            .decrypt(&snapshot.secrets)
+            .map_err(Error::invalid_snapshot)?;
+        let mut secrets: SnapshotSecrets =
+            bincode::deserialize(&ser_secrets).map_err(Error::invalid_snapshot)?;
+        let mut key_gen = SyncKeyGen::create(
+            snapshot.our_id,
+            sec_key,
+            snapshot.pub_keys,
+            snapshot.threshold,
+        );
+        for (idx, (commit, acks)) in snapshot.parts {
+            let values = secrets.values.remove(&idx).unwrap_or_default();
+            let values = values
+                .into_iter()
+                .map(|(val_idx, val)| (val_idx, val.into_inner()))
+                .collect();
+            let part = ProposalState {
+                commit,
+                values,
+                acks,
+            };
+            key_gen.parts.insert(idx, part);
+        }
+        key_gen.disqualified = snapshot.disqualified;
+        key_gen.transcript = snapshot.transcript;
+        key_gen.our_rows = secrets.our_rows;
+        let key_share = secrets.key_share.map(|val| KeyShare(val.into_inner()));
+        key_gen.refresh = snapshot.refresh.map(|refresh| RefreshState {
+            commit: refresh.commit,
+            key_share,
+            offsets: refresh
+                .offsets
+                .into_iter()
+                .map(|(idx, offset)| (idx, offset.into_inner()))
+                .collect(),
+            dealers: refresh.dealers,
+        });
+        Ok((key_gen, snapshot.pending_acks))
+    }
+
     /// Handles a `Part` message, or returns a `PartFault` if it is invalid.
     fn handle_part_or_fault(
         &mut self,
@@ -375,6 +1674,9 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         if rows.len() != self.pub_keys.len() {
             return Err(PartFault::RowCount);
         }
+        if commit.degree() != self.threshold {
+            return Err(PartFault::CommitmentDegree);
+        }
         if let Some(state) = self.parts.get(&sender_idx) {
             if state.commit != commit {
                 return Err(PartFault::MultipleParts);
@@ -409,6 +1711,9 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
         if values.len() != self.pub_keys.len() {
             return Err(AckFault::ValueCount);
         }
+        if self.disqualified.contains(&proposer_idx) {
+            return Ok(()); // The part was discarded after a complaint.
+        }
         let part = self
             .parts
             .get_mut(&proposer_idx)
@@ -436,8 +1741,501 @@ impl<N: NodeIdT, PK: PublicKey> SyncKeyGen<N, PK> {
     }
 }
 
+/// The set of parts that make up the generated key, agreed on by all nodes.
+///
+/// In the asynchronous mode, every node reports the parts that are complete from its point of
+/// view. A coordinator multicasts the intersection of the reports, and all nodes generate the key
+/// from exactly these parts.
+#[derive(Deserialize, Serialize, Clone, Debug, Hash, Eq, PartialEq)]
+pub struct QualifiedSet(BTreeSet<u64>);
+
+impl QualifiedSet {
+    /// Returns the parts contained in both sets.
+    pub fn intersection(&self, other: &QualifiedSet) -> QualifiedSet {
+        QualifiedSet(self.0.intersection(&other.0).cloned().collect())
+    }
+
+    /// Returns `true` if the part of the node with the given index is in the set.
+    pub fn contains(&self, proposer_idx: u64) -> bool {
+        self.0.contains(&proposer_idx)
+    }
+
+    /// Returns the number of parts in the set.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the set contains no parts.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A message handled during a key generation, as recorded in a `Transcript`.
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub enum TranscriptMessage {
+    /// A `Part`, or the `Part` of a `RefreshPart`.
+    Part(Part),
+    /// An `Ack`.
+    Ack(Ack),
+    /// A judged `Complaint`, with the accused proposer's `Justification`, if it answered.
+    Complaint(Complaint, Option<Justification>),
+}
+
+/// An entry of a `Transcript`: a message with its sender, and a hash that chains it to all
+/// previous entries.
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct TranscriptEntry {
+    sender_idx: u64,
+    message: TranscriptMessage,
+    hash: [u8; 32],
+}
+
+impl TranscriptEntry {
+    /// Returns the index of the sender: the proposer index for a `Part`, and the node index
+    /// otherwise.
+    pub fn sender_idx(&self) -> u64 {
+        self.sender_idx
+    }
+
+    /// Returns the recorded message.
+    pub fn message(&self) -> &TranscriptMessage {
+        &self.message
+    }
+
+    /// Returns the hash of this entry, which commits to all entries up to this one.
+    pub fn hash(&self) -> &[u8; 32] {
+        &self.hash
+    }
+}
+
+/// The ordered record of all messages handled during a key generation.
+///
+/// Each entry is hashed together with the previous entry's hash, so the `head` of the transcript
+/// commits to all of it. The transcript only contains public messages: it can be handed to
+/// auditors who don't hold a share, and who `verify` that a published key came from it.
+#[derive(Deserialize, Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transcript(Vec<TranscriptEntry>);
+
+impl Transcript {
+    /// Returns all entries, in the order the messages were handled.
+    pub fn entries(&self) -> &[TranscriptEntry] {
+        &self.0
+    }
+
+    /// Returns the hash of the last entry, or zeros if the transcript is empty.
+    pub fn head(&self) -> [u8; 32] {
+        self.0.last().map_or([0; 32], |entry| entry.hash)
+    }
+
+    /// Appends a message to the transcript.
+    fn push(&mut self, sender_idx: u64, message: TranscriptMessage) -> Result<(), Error> {
+        let hash = transcript_hash(&self.head(), sender_idx, &message)?;
+        self.0.push(TranscriptEntry {
+            sender_idx,
+            message,
+            hash,
+        });
+        Ok(())
+    }
+
+    /// Replays the public checks of every message, and returns the public key set generated
+    /// from the complete parts.
+    ///
+    /// Without a `qualified` set, all complete parts are used, like in `SyncKeyGen::generate`.
+    /// Otherwise only the agreed parts are used, like in `AsyncKeyGen::generate`. Invalid
+    /// messages are skipped, as the nodes skip them. Fails with `InvalidTranscript` if an entry
+    /// doesn't match the hash chain.
+    ///
+    /// Only the transcript of a new key can be verified: a share refresh or resharing also
+    /// depends on the previous key.
+    pub fn verify(
+        &self,
+        params: ThresholdParams,
+        qualified: Option<&QualifiedSet>,
+    ) -> Result<PublicKeySet, Error> {
+        let ThresholdParams {
+            threshold,
+            num_nodes,
+        } = params;
+        let mut parts: BTreeMap<u64, (BivarCommitment, BTreeSet<u64>)> = BTreeMap::new();
+        let mut disqualified = BTreeSet::new();
+        let mut prev_hash = [0; 32];
+        for (i, entry) in self.0.iter().enumerate() {
+            let sender_idx = entry.sender_idx;
+            if transcript_hash(&prev_hash, sender_idx, &entry.message)? != entry.hash {
+                return Err(Error::InvalidTranscript(i));
+            }
+            prev_hash = entry.hash;
+            if sender_idx >= num_nodes as u64 {
+                continue; // The nodes refuse unknown senders.
+            }
+            match &entry.message {
+                TranscriptMessage::Part(Part(commit, rows)) => {
+                    if rows.len() == num_nodes && commit.degree() == threshold {
+                        let new_part = || (commit.clone(), BTreeSet::new());
+                        parts.entry(sender_idx).or_insert_with(new_part);
+                    }
+                }
+                TranscriptMessage::Ack(Ack(proposer_idx, values)) => {
+                    if values.len() != num_nodes || disqualified.contains(proposer_idx) {
+                        continue;
+                    }
+                    if let Some((_, acks)) = parts.get_mut(proposer_idx) {
+                        acks.insert(sender_idx);
+                    }
+                }
+                TranscriptMessage::Complaint(complaint, opt_justification) => {
+                    let Complaint(proposer_idx, Part(commit, _)) = complaint;
+                    let is_upheld = match parts.get(proposer_idx) {
+                        None => true,
+                        Some((part_commit, _)) if part_commit != commit => false,
+                        Some((part_commit, _)) => {
+                            let commit_row = part_commit.row(sender_idx + 1);
+                            let is_valid = |Justification(idx, row): &Justification| {
+                                *idx == sender_idx && row.commitment() == commit_row
+                            };
+                            !opt_justification.as_ref().map_or(false, is_valid)
+                        }
+                    };
+                    if is_upheld {
+                        parts.remove(proposer_idx);
+                        disqualified.insert(*proposer_idx);
+                    }
+                }
+            }
+        }
+
+        let is_complete = |idx: &u64| {
+            parts
+                .get(idx)
+                .map_or(false, |(_, acks)| acks.len() > threshold)
+        };
+        let indices: Vec<u64> = match qualified {
+            Some(qualified) => {
+                if let Some(idx) = qualified.0.iter().find(|idx| !is_complete(*idx)) {
+                    return Err(Error::IncompletePart(*idx));
+                }
+                qualified.0.iter().cloned().collect()
+            }
+            None => parts.keys().cloned().filter(is_complete).collect(),
+        };
+        if indices.len() <= threshold {
+            return Err(Error::QualifiedSetSize(indices.len()));
+        }
+        let mut pk_commit = Poly::zero().commitment();
+        for idx in indices {
+            pk_commit += parts[&idx].0.row(0);
+        }
+        Ok(pk_commit.into())
+    }
+}
+
+/// Returns the hash of a transcript entry, chained to the hash of the previous entry.
+fn transcript_hash(
+    prev_hash: &[u8; 32],
+    sender_idx: u64,
+    message: &TranscriptMessage,
+) -> Result<[u8; 32], Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(prev_hash);
+    hasher.update(bincode::serialize(&(sender_idx, message))?);
+    Ok(hasher.finalize().into())
+}
+
+/// An asynchronous algorithm for dealerless distributed key generation, built on `SyncKeyGen`.
+///
+/// Nodes can handle `Part` and `Ack` messages in any order: an `Ack` that arrives before its
+/// `Part` is buffered until the `Part` is handled. Since nodes may then see different parts
+/// complete at any given time, the key is generated from an agreed `QualifiedSet` instead of all
+/// complete parts.
+#[derive(Debug)]
+pub struct AsyncKeyGen<N, PK: PublicKey = threshold_crypto::PublicKey> {
+    /// The underlying synchronous key generation.
+    key_gen: SyncKeyGen<N, PK>,
+    /// `Ack`s whose `Part` we haven't handled yet, by proposer index.
+    pending_acks: BTreeMap<u64, Vec<(N, Ack)>>,
+}
+
+impl<N: NodeIdT, PK: PublicKey> From<SyncKeyGen<N, PK>> for AsyncKeyGen<N, PK> {
+    fn from(key_gen: SyncKeyGen<N, PK>) -> Self {
+        AsyncKeyGen {
+            key_gen,
+            pending_acks: BTreeMap::new(),
+        }
+    }
+}
+
+impl<N: NodeIdT, PK: PublicKey> AsyncKeyGen<N, PK> {
+    /// Creates a new `AsyncKeyGen` instance, together with the `Part` message that should be
+    /// multicast to all nodes. See `SyncKeyGen::new`.
+    pub fn new<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        threshold: usize,
+        rng: &mut R,
+    ) -> Result<(Self, Option<Part>), Error> {
+        let (key_gen, opt_part) = SyncKeyGen::new(our_id, sec_key, pub_keys, threshold, rng)?;
+        Ok((AsyncKeyGen::from(key_gen), opt_part))
+    }
+
+    /// Creates a new `AsyncKeyGen` instance that refreshes the shares of an existing key,
+    /// together with the `RefreshPart` message that should be multicast to all nodes. See
+    /// `SyncKeyGen::refresh`.
+    pub fn refresh<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        pub_key_set: &PublicKeySet,
+        key_share: Option<&KeyShare>,
+        rng: &mut R,
+    ) -> Result<(Self, Option<RefreshPart>), Error> {
+        let (key_gen, opt_part) =
+            SyncKeyGen::refresh(our_id, sec_key, pub_keys, pub_key_set, key_share, rng)?;
+        Ok((AsyncKeyGen::from(key_gen), opt_part))
+    }
+
+    /// Creates a new `AsyncKeyGen` instance that reshares an existing key to a new committee,
+    /// together with the `RefreshPart` message that should be multicast to all nodes. See
+    /// `SyncKeyGen::reshare`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn reshare<R: rand::Rng>(
+        our_id: N,
+        sec_key: PK::SecretKey,
+        pub_keys: PubKeyMap<N, PK>,
+        threshold: usize,
+        old_pub_keys: &PubKeyMap<N, PK>,
+        pub_key_set: &PublicKeySet,
+        key_share: Option<&KeyShare>,
+        rng: &mut R,
+    ) -> Result<(Self, Option<RefreshPart>), Error> {
+        let (key_gen, opt_part) = SyncKeyGen::reshare(
+            our_id,
+            sec_key,
+            pub_keys,
+            threshold,
+            old_pub_keys,
+            pub_key_set,
+            key_share,
+            rng,
+        )?;
+        Ok((AsyncKeyGen::from(key_gen), opt_part))
+    }
+
+    /// Returns the id of this node.
+    pub fn our_id(&self) -> &N {
+        self.key_gen.our_id()
+    }
+
+    /// Returns the map of participating nodes and their public keys.
+    pub fn public_keys(&self) -> &PubKeyMap<N, PK> {
+        self.key_gen.public_keys()
+    }
+
+    /// Returns `true` if we are an observer, and don't receive a key share.
+    pub fn is_observer(&self) -> bool {
+        self.key_gen.is_observer()
+    }
+
+    /// Returns the transcript of all messages we handled so far. Buffered `Ack`s are recorded
+    /// once they are handled.
+    pub fn transcript(&self) -> &Transcript {
+        self.key_gen.transcript()
+    }
+
+    /// Returns the `t`-of-`n` parameters of the key being generated.
+    pub fn params(&self) -> ThresholdParams {
+        self.key_gen.params()
+    }
+
+    /// Returns the number of complete parts needed to generate the key. See
+    /// `SyncKeyGen::min_parts`.
+    pub fn min_parts(&self) -> usize {
+        self.key_gen.min_parts()
+    }
+
+    /// Returns a snapshot of our state, including the buffered `Ack`s. See
+    /// `SyncKeyGen::snapshot`.
+    pub fn snapshot<R: rand::Rng>(
+        &self,
+        our_pub_key: &PK,
+        rng: &mut R,
+    ) -> Result<KeyGenSnapshot<N, PK>, Error> {
+        let mut snapshot = self.key_gen.snapshot(our_pub_key, rng)?;
+        snapshot.pending_acks = self.pending_acks.clone();
+        Ok(snapshot)
+    }
+
+    /// Restores an instance from a `snapshot` of its state, given our secret key. See
+    /// `SyncKeyGen::restore`.
+    pub fn restore(snapshot: KeyGenSnapshot<N, PK>, sec_key: PK::SecretKey) -> Result<Self, Error> {
+        let (key_gen, pending_acks) = SyncKeyGen::restore_with_pending_acks(snapshot, sec_key)?;
+        Ok(AsyncKeyGen {
+            key_gen,
+            pending_acks,
+        })
+    }
+
+    /// Handles a `Part` message, and then the buffered `Ack`s for it. Returns the outcome for
+    /// the `Part`, and the invalid buffered `Ack`s with their faults, by sender.
+    pub fn handle_part<R: rand::Rng>(
+        &mut self,
+        sender_id: &N,
+        part: Part,
+        rng: &mut R,
+    ) -> Result<(PartOutcome, Vec<(N, Ack, AckFault)>), Error> {
+        let outcome = self.key_gen.handle_part(sender_id, part, rng)?;
+        let faults = self.handle_pending_acks(sender_id)?;
+        Ok((outcome, faults))
+    }
+
+    /// Handles a `RefreshPart` message, and then the buffered `Ack`s for it. See
+    /// `SyncKeyGen::handle_refresh_part`.
+    pub fn handle_refresh_part<R: rand::Rng>(
+        &mut self,
+        sender_id: &N,
+        part: RefreshPart,
+        rng: &mut R,
+    ) -> Result<(PartOutcome, Vec<(N, Ack, AckFault)>), Error> {
+        let outcome = self.key_gen.handle_refresh_part(sender_id, part, rng)?;
+        let faults = self.handle_pending_acks(sender_id)?;
+        Ok((outcome, faults))
+    }
+
+    /// Handles the buffered `Ack`s for the sender's `Part`, if we accepted it, and returns the
+    /// invalid ones with their faults, by sender.
+    fn handle_pending_acks(&mut self, sender_id: &N) -> Result<Vec<(N, Ack, AckFault)>, Error> {
+        let sender_idx = self
+            .key_gen
+            .proposer_index(sender_id)
+            .ok_or(Error::UnknownSender)?;
+        let mut faults = Vec::new();
+        if self.key_gen.parts.contains_key(&sender_idx) {
+            let pending_acks = self.pending_acks.remove(&sender_idx).unwrap_or_default();
+            for (ack_sender_id, ack) in pending_acks {
+                let outcome = self.key_gen.handle_ack(&ack_sender_id, ack.clone())?;
+                if let AckOutcome::Invalid(fault) = outcome {
+                    faults.push((ack_sender_id, ack, fault));
+                }
+            }
+        }
+        Ok(faults)
+    }
+
+    /// Handles an `Ack` message. Returns `None` if the `Ack` was buffered because we haven't
+    /// handled its `Part` yet.
+    pub fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<Option<AckOutcome>, Error> {
+        let proposer_idx = ack.0;
+        let is_pending = (proposer_idx as usize) < self.key_gen.proposer_ids().len()
+            && !self.key_gen.parts.contains_key(&proposer_idx)
+            && !self.key_gen.disqualified.contains(&proposer_idx);
+        if !is_pending {
+            return self.key_gen.handle_ack(sender_id, ack).map(Some);
+        }
+        self.key_gen
+            .node_index(sender_id)
+            .ok_or(Error::UnknownSender)?;
+        self.pending_acks
+            .entry(proposer_idx)
+            .or_default()
+            .push((sender_id.clone(), ack));
+        Ok(None)
+    }
+
+    /// Returns a `Complaint` against the sender of an invalid `Part`. See `SyncKeyGen::complain`.
+    pub fn complain(&self, sender_id: &N, part: Part) -> Result<Complaint, Error> {
+        self.key_gen.complain(sender_id, part)
+    }
+
+    /// Returns our answer to a `Complaint`. See `SyncKeyGen::justify`.
+    pub fn justify(&self, accuser_id: &N) -> Result<Option<Justification>, Error> {
+        self.key_gen.justify(accuser_id)
+    }
+
+    /// Judges a `Complaint`. See `SyncKeyGen::handle_complaint`.
+    ///
+    /// Unlike `Part`s and `Ack`s, complaints must still be judged in the same order by all nodes.
+    pub fn handle_complaint<R: rand::Rng>(
+        &mut self,
+        accuser_id: &N,
+        complaint: Complaint,
+        opt_justification: Option<Justification>,
+        rng: &mut R,
+    ) -> Result<ComplaintOutcome, Error> {
+        self.key_gen
+            .handle_complaint(accuser_id, complaint, opt_justification, rng)
+    }
+
+    /// Returns the IDs of the proposers whose part was discarded after a complaint.
+    pub fn disqualified_nodes(&self) -> Vec<N> {
+        self.key_gen.disqualified_nodes()
+    }
+
+    /// Returns the ID of the proposer with the given index, e.g. the one a `Complaint` accuses.
+    pub fn proposer_id(&self, proposer_idx: u64) -> Option<N> {
+        self.key_gen.proposer_id(proposer_idx)
+    }
+
+    /// Returns the IDs of the proposers whose `Part` we haven't handled. See
+    /// `SyncKeyGen::missing_parts`.
+    pub fn missing_parts(&self) -> Vec<N> {
+        self.key_gen.missing_parts()
+    }
+
+    /// Returns the IDs of the nodes from which we haven't handled any `Ack`. Buffered `Ack`s
+    /// don't count until their `Part` is handled.
+    pub fn missing_acks(&self) -> Vec<N> {
+        self.key_gen.missing_acks()
+    }
+
+    /// Returns the parts that are complete from our point of view, to be reported to the
+    /// coordinator.
+    pub fn qualified_set(&self) -> QualifiedSet {
+        let parts = &self.key_gen.parts;
+        let usable = parts
+            .iter()
+            .filter(|(_, part)| self.key_gen.is_part_usable(part))
+            .map(|(idx, _)| *idx);
+        QualifiedSet(usable.collect())
+    }
+
+    /// Returns the new secret key share and the public key set, generated from the parts in the
+    /// agreed `QualifiedSet`.
+    ///
+    /// Fails with `IncompletePart` if we haven't handled enough `Ack`s for one of these parts
+    /// yet. In that case, the call must be repeated after handling more messages.
+    pub fn generate(
+        &self,
+        qualified: &QualifiedSet,
+    ) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error> {
+        let (pub_key_set, opt_key_share) = self.generate_key_share(qualified)?;
+        Ok((pub_key_set, opt_key_share.map(|ks| ks.secret_key_share())))
+    }
+
+    /// Returns the new `KeyShare` and the public key set, like `generate`. Unlike a
+    /// `SecretKeyShare`, the `KeyShare` can be refreshed later.
+    pub fn generate_key_share(
+        &self,
+        qualified: &QualifiedSet,
+    ) -> Result<(PublicKeySet, Option<KeyShare>), Error> {
+        if qualified.len() < self.key_gen.min_parts() {
+            return Err(Error::QualifiedSetSize(qualified.len()));
+        }
+        let mut parts = Vec::new();
+        for idx in &qualified.0 {
+            match self.key_gen.parts.get(idx) {
+                Some(part) if self.key_gen.is_part_usable(part) => parts.push((idx, part)),
+                _ => return Err(Error::IncompletePart(*idx)),
+            }
+        }
+        let (pk_commit, opt_sk_val) = self.key_gen.sum_parts(parts);
+        Ok((pk_commit.into(), opt_sk_val.map(KeyShare)))
+    }
+}
+
 /// An error in an `Ack` message sent by a faulty node.
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Fail)]
+#[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Debug, Fail)]
 pub enum AckFault {
     /// The number of values differs from the number of nodes.
     #[fail(display = "The number of values differs from the number of nodes")]
@@ -457,7 +2255,7 @@ pub enum AckFault {
 }
 
 /// An error in a `Part` message sent by a faulty node.
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Fail)]
+#[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Debug, Fail)]
 pub enum PartFault {
     /// The number of rows differs from the number of nodes.
     #[fail(display = "The number of rows differs from the number of nodes")]
@@ -474,4 +2272,192 @@ pub enum PartFault {
     /// Row does not match the commitment.
     #[fail(display = "Row does not match the commitment")]
     RowCommitment,
+    /// The degree of the commitment differs from the threshold.
+    #[fail(display = "The degree of the commitment differs from the threshold")]
+    CommitmentDegree,
+    /// The offset of a refresh or resharing part is missing or doesn't match the commitment.
+    #[fail(display = "Invalid offset in the refresh Part")]
+    RefreshConstant,
+}
+
+/// The phase of a key generation in which a fault was detected.
+#[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Phase {
+    /// Handling the `Part`s, and the `Ack`s that were buffered for them.
+    Commit,
+    /// Judging the complaints.
+    Judge,
+    /// Handling the `Ack`s.
+    Ack,
+}
+
+/// The kind of a fault detected in a key generation.
+#[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Debug, Fail)]
+pub enum FaultKind {
+    /// An invalid `Part`.
+    #[fail(display = "{}", _0)]
+    Part(PartFault),
+    /// An invalid `Ack`.
+    #[fail(display = "{}", _0)]
+    Ack(AckFault),
+    /// A `Complaint` was upheld: the proposer didn't justify its part.
+    #[fail(display = "The proposer didn't justify its part")]
+    ComplaintUpheld,
+}
+
+/// A fault of a node, detected in a key generation.
+///
+/// Faulty nodes should be excluded from the next key generation.
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq, Debug)]
+pub struct Fault<N> {
+    /// The faulty node.
+    pub node_id: N,
+    /// What was wrong with the node's message.
+    pub kind: FaultKind,
+    /// The SHA-256 hash of the serialized message: the `Part`, `Ack` or `Complaint`.
+    pub msg_hash: [u8; 32],
+    /// The phase in which the fault was detected.
+    pub phase: Phase,
+}
+
+impl<N> Fault<N> {
+    /// Creates a report of a fault in the given message.
+    pub fn new<M: Serialize>(
+        node_id: N,
+        kind: FaultKind,
+        msg: &M,
+        phase: Phase,
+    ) -> Result<Self, Error> {
+        let msg_hash = Sha256::digest(bincode::serialize(msg)?).into();
+        Ok(Fault {
+            node_id,
+            kind,
+            msg_hash,
+            phase,
+        })
+    }
+}
+
+/// Returns the Lagrange coefficient at `0` of the proposer with index `idx`, among the proposers
+/// with the given indices. Proposer `i` is evaluated at `i + 1`.
+fn lagrange_coefficient(idx: u64, indices: &[u64]) -> Fr {
+    let x_idx = (idx + 1).into_fr();
+    let mut numerator = Fr::one();
+    let mut denominator = Fr::one();
+    for &other_idx in indices.iter().filter(|other_idx| **other_idx != idx) {
+        let x_other = (other_idx + 1).into_fr();
+        numerator.mul_assign(&x_other);
+        let mut diff = x_other;
+        diff.sub_assign(&x_idx);
+        denominator.mul_assign(&diff);
+    }
+    let inverse = denominator
+        .inverse()
+        .expect("proposer indices are distinct");
+    numerator.mul_assign(&inverse);
+    numerator
+}
+
+/// Returns the commitment multiplied by a scalar. `Commitment` only supports addition, so this
+/// doubles and adds, starting with the most significant bit.
+fn mul_commitment(commit: &Commitment, scalar: Fr) -> Commitment {
+    let mut result = Poly::zero().commitment();
+    let repr = scalar.into_repr();
+    let limbs: &[u64] = repr.as_ref();
+    for limb in limbs.iter().rev() {
+        for bit in (0..64).rev() {
+            let doubled = result.clone();
+            result += doubled;
+            if (limb >> bit) & 1 == 1 {
+                result += commit;
+            }
+        }
+    }
+    result
+}
+
+/// Returns the commitment to the polynomial of a public key set. `PublicKeySet` has no accessor,
+/// but it is serialized as exactly that commitment.
+fn pub_key_set_commitment(pub_key_set: &PublicKeySet) -> Result<Commitment, Error> {
+    Ok(bincode::deserialize(&bincode::serialize(pub_key_set)?)?)
+}
+
+/// Returns a uniformly random field element.
+fn random_fr<R: Rng>(rng: &mut R) -> Fr {
+    Poly::random(0, rng).evaluate(0)
+}
+
+/// Returns the compressed encoding of the point.
+fn g1_to_bytes(point: &G1) -> Vec<u8> {
+    point.into_affine().into_compressed().as_ref().to_vec()
+}
+
+/// Returns the point with the given compressed encoding, or `None` if it is invalid or zero.
+fn g1_from_bytes(bytes: &[u8]) -> Option<G1> {
+    let mut compressed = G1Compressed::empty();
+    if bytes.len() != compressed.as_ref().len() {
+        return None;
+    }
+    compressed.as_mut().copy_from_slice(bytes);
+    let point = compressed.into_affine().ok()?.into_projective();
+    Some(point).filter(|point| !point.is_zero())
+}
+
+/// Returns `response * base - challenge * point`: the commitment of a Schnorr proof that `point`
+/// is a multiple of `base`.
+fn schnorr_commitment(base: G1, point: G1, challenge: Fr, response: Fr) -> G1 {
+    let mut commit = base;
+    commit.mul_assign(response);
+    let mut subtrahend = point;
+    subtrahend.mul_assign(challenge);
+    commit.sub_assign(&subtrahend);
+    commit
+}
+
+/// Returns the challenge of the proof in a `LabelledCiphertext`.
+fn ciphertext_challenge(u: &[u8], v: &[u8], label: &[u8], commit: &G1) -> Fr {
+    let commit = g1_to_bytes(commit);
+    hash_to_fr(b"labelled ciphertext", &[u, v, label, &commit])
+}
+
+/// Returns the challenge of the proof in a `LabelledDecryptionShare`.
+fn share_challenge(u: &[u8], pub_key_share: &G1, point: &G1, commit_g: &G1, commit_u: &G1) -> Fr {
+    let points = [pub_key_share, point, commit_g, commit_u].map(g1_to_bytes);
+    hash_to_fr(
+        b"labelled decryption share",
+        &[u, &points[0], &points[1], &points[2], &points[3]],
+    )
+}
+
+/// Hashes the domain and the length-prefixed items to a field element.
+fn hash_to_fr(domain: &[u8], items: &[&[u8]]) -> Fr {
+    let mut hasher = Sha256::new();
+    hasher.update(domain);
+    for item in items {
+        hasher.update((item.len() as u64).to_be_bytes());
+        hasher.update(item);
+    }
+    let digest = hasher.finalize();
+    let mut limbs = [0u64; 4];
+    for (limb, bytes) in limbs.iter_mut().zip(digest.chunks(8)) {
+        *limb = u64::from_le_bytes(bytes.try_into().expect("8 bytes"));
+    }
+    // Clearing the top two bits keeps the value below the 255-bit modulus.
+    limbs[3] &= u64::MAX >> 2;
+    Fr::from_repr(FrRepr(limbs)).expect("the value is below the modulus")
+}
+
+/// Xors the message with a SHA-256 key stream derived from the point.
+fn xor_with_key_stream(point: &G1, msg: &[u8]) -> Vec<u8> {
+    let seed = g1_to_bytes(point);
+    let mut result = Vec::with_capacity(msg.len());
+    for (counter, chunk) in msg.chunks(32).enumerate() {
+        let mut hasher = Sha256::new();
+        hasher.update(b"labelled key stream");
+        hasher.update(&seed);
+        hasher.update((counter as u64).to_be_bytes());
+        let block = hasher.finalize();
+        result.extend(chunk.iter().zip(block.iter()).map(|(m, k)| m ^ k));
+    }
+    result
 }
\ No newline at end of file
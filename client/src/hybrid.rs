@@ -0,0 +1,155 @@
+//! Hybrid encryption to a threshold key: a random data key is encrypted to the master public key,
+//! and the payload is encrypted with ChaCha20-Poly1305 under the data key. Unlike
+//! `PublicKey::encrypt`, this authenticates caller-supplied associated data, and the threshold
+//! decryption round only has to handle the 32 bytes of the data key, whatever the payload size.
+
+use std::fmt::{self, Debug, Formatter};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use threshold_crypto::{Ciphertext, DecryptionShare, IntoFr, PublicKey, PublicKeySet};
+
+/// The length of a data key, in bytes.
+pub const KEY_LEN: usize = 32;
+/// The length of a ChaCha20-Poly1305 nonce, in bytes.
+pub const NONCE_LEN: usize = 12;
+
+/// An error while encrypting or decrypting a `HybridCiphertext`.
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum Error {
+    /// The threshold-encrypted data key is not well formed.
+    #[fail(display = "Invalid data key ciphertext")]
+    InvalidKeyCiphertext,
+    /// The decryption shares can't be combined.
+    #[fail(display = "Error combining decryption shares: {}", _0)]
+    Combine(String),
+    /// The decrypted data key has the wrong length.
+    #[fail(display = "Invalid data key")]
+    InvalidDataKey,
+    /// The payload or the associated data have been tampered with, or the data key is wrong.
+    #[fail(display = "Authentication failed")]
+    Authentication,
+    /// Failed to serialize the associated data.
+    #[fail(display = "Serialization error: {}", _0)]
+    Serialize(String),
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Error {
+        Error::Serialize(format!("{:?}", err))
+    }
+}
+
+/// A payload encrypted under a fresh data key, together with the data key encrypted to a
+/// threshold public key.
+///
+/// The data key ciphertext is part of the AEAD's associated data, so tampering with either half
+/// makes decryption fail.
+#[derive(Deserialize, Serialize, Clone, Eq, PartialEq)]
+pub struct HybridCiphertext {
+    key_ct: Ciphertext,
+    nonce: [u8; NONCE_LEN],
+    payload: Vec<u8>,
+}
+
+impl Debug for HybridCiphertext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HybridCiphertext")
+            .field("key_ct", &self.key_ct)
+            .field("payload", &format!("<{} bytes>", self.payload.len()))
+            .finish()
+    }
+}
+
+impl HybridCiphertext {
+    /// Encrypts the message to the public key, authenticating the associated data `ad`. The same
+    /// `ad` must be supplied to decrypt it.
+    pub fn encrypt<M: AsRef<[u8]>, R: Rng>(
+        pk: &PublicKey,
+        msg: M,
+        ad: &[u8],
+        rng: &mut R,
+    ) -> Result<HybridCiphertext, Error> {
+        let data_key: [u8; KEY_LEN] = rng.gen();
+        let key_ct = pk.encrypt_with_rng(rng, data_key);
+        let nonce: [u8; NONCE_LEN] = rng.gen();
+        let aad = associated_data(&key_ct, ad)?;
+        let payload = Payload {
+            msg: msg.as_ref(),
+            aad: &aad,
+        };
+        let payload = cipher(&data_key)?
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Authentication)?;
+        Ok(HybridCiphertext {
+            key_ct,
+            nonce,
+            payload,
+        })
+    }
+
+    /// Returns the data key ciphertext. The decryption shares are computed for it.
+    pub fn key_ciphertext(&self) -> &Ciphertext {
+        &self.key_ct
+    }
+
+    /// Returns `true` if the data key ciphertext is well formed. The payload can only be checked
+    /// with the data key.
+    pub fn verify(&self) -> bool {
+        self.key_ct.verify()
+    }
+
+    /// Combines at least `threshold + 1` decryption shares of the data key ciphertext, by share
+    /// index, and decrypts the payload.
+    ///
+    /// The shares are not verified: the caller must check them with
+    /// `PublicKeyShare::verify_decryption_share` first.
+    pub fn decrypt<'a, T, I>(
+        &self,
+        pub_key_set: &PublicKeySet,
+        shares: I,
+        ad: &[u8],
+    ) -> Result<Vec<u8>, Error>
+    where
+        I: IntoIterator<Item = (T, &'a DecryptionShare)>,
+        T: IntoFr,
+    {
+        if !self.verify() {
+            return Err(Error::InvalidKeyCiphertext);
+        }
+        let data_key = pub_key_set
+            .decrypt(shares, &self.key_ct)
+            .map_err(|err| Error::Combine(err.to_string()))?;
+        self.decrypt_with_data_key(&data_key, ad)
+    }
+
+    /// Decrypts the payload with the data key.
+    fn decrypt_with_data_key(&self, data_key: &[u8], ad: &[u8]) -> Result<Vec<u8>, Error> {
+        let aad = associated_data(&self.key_ct, ad)?;
+        let payload = Payload {
+            msg: &self.payload,
+            aad: &aad,
+        };
+        cipher(data_key)?
+            .decrypt(Nonce::from_slice(&self.nonce), payload)
+            .map_err(|_| Error::Authentication)
+    }
+}
+
+/// Returns the AEAD cipher for the data key.
+fn cipher(data_key: &[u8]) -> Result<ChaCha20Poly1305, Error> {
+    if data_key.len() != KEY_LEN {
+        return Err(Error::InvalidDataKey);
+    }
+    Ok(ChaCha20Poly1305::new(Key::from_slice(data_key)))
+}
+
+/// Returns the AEAD's associated data: the caller's `ad`, bound to the data key ciphertext.
+fn associated_data(key_ct: &Ciphertext, ad: &[u8]) -> Result<Vec<u8>, Error> {
+    Ok(bincode::serialize(&(key_ct, ad))?)
+}
@@ -1,47 +1,170 @@
+pub mod base64_bytes;
+pub mod cli;
 pub mod dkg;
+pub mod envelope;
+pub mod hybrid;
+pub mod stream;
 use axum::{
-    error_handling::HandleErrorLayer, extract::State, http::StatusCode, response::IntoResponse,
-    routing::post, Json, Router,
+    error_handling::HandleErrorLayer, extract::State, http::StatusCode, routing::post, Json, Router,
 };
 use axum_macros::debug_handler;
-use dkg::{Ack, AckOutcome, Part, PartOutcome, PubKeyMap, SyncKeyGen};
+use dkg::{
+    Ack, BeaconRound, BeaconSchedule, Complaint, Fault, Justification, LabelledCiphertext,
+    LabelledDecryptionShare, Part, QualifiedSet, RefreshPart, SigningContext, ThresholdParams,
+    Transcript, DEFAULT_BEACON_PERIOD_SECS, DEFAULT_MIN_QUORUM,
+};
+use envelope::Envelope;
+use hybrid::HybridCiphertext;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
     error::Error,
     net::SocketAddr,
     sync::{Arc, RwLock},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use threshold_crypto::{SecretKey, SignatureShare};
-use tokio::sync::Mutex;
+use threshold_crypto::{Ciphertext, DecryptionShare, PublicKeySet, Signature, SignatureShare};
+use tokio::{task::JoinSet, time::Instant};
 use tower::{BoxError, ServiceBuilder};
 use tower_http::trace::TraceLayer;
 
 type Db = Arc<RwLock<HashMap<usize, Session>>>;
-const SERVER_URL: &str = "http://127.0.0.1:3002";
+
+/// How often we try to reach an accused proposer for its justification.
+const JUSTIFY_ATTEMPTS: u64 = 3;
+
+/// The most ciphertexts we decrypt in one batch, so that a batch finishes within the request
+/// timeout.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// The faults detected by each node, by the reporting node's ID.
+type FaultReports = BTreeMap<usize, Vec<Fault<usize>>>;
+
+/// The orchestrator's view of a DKG ceremony.
 #[derive(Debug, Clone)]
 struct Session {
-    sk: SecretKey,
-    node: Arc<Mutex<SyncKeyGen<usize>>>,
-    parts: Vec<Part>,
-    acks: Vec<Ack>,
+    /// The URL of every committee member, by node ID.
+    members: BTreeMap<usize, String>,
+    /// The URL of every node that deals a part, by node ID. In a resharing, these are the
+    /// current share holders instead of the members.
+    dealers: BTreeMap<usize, String>,
+    /// The URL of every observer, by node ID. Observers follow the ceremony and get the public
+    /// key set, but no key share.
+    observers: BTreeMap<usize, String>,
+    params: ThresholdParams,
+    /// The number of complete parts needed to generate the key.
+    min_parts: usize,
+    parts: BTreeMap<usize, Part>,
+    acks: Vec<(usize, Ack)>,
+    /// The members whose part was discarded after a complaint.
+    disqualified: Vec<usize>,
+    /// The parts the key was generated from, once it is finalized.
+    qualified: Option<QualifiedSet>,
+    pub_key_set: Option<PublicKeySet>,
+    /// The members that missed a deadline. They are skipped in all later phases.
+    absent: BTreeSet<usize>,
+    /// The rounds of the randomness beacon run with the session's key, from round 0.
+    beacon: Vec<BeaconRound>,
 }
 
+impl Session {
+    /// Returns the URL of every member that hasn't missed a deadline, by node ID.
+    fn present_members(&self) -> BTreeMap<usize, String> {
+        let is_present = |(id, _): &(&usize, &String)| !self.absent.contains(id);
+        let present = self.members.iter().filter(is_present);
+        present.map(|(id, url)| (*id, url.clone())).collect()
+    }
+
+    /// Returns the number of complete parts we accept to generate the key from: at least
+    /// `min_parts`, and at least the configured `min_complete`.
+    fn min_complete(&self, state: &AppState) -> usize {
+        self.min_parts.max(state.min_complete)
+    }
+}
+
+/// The state shared by all handlers of the orchestrator.
+#[derive(Debug, Clone)]
+struct AppState {
+    /// The smallest `threshold + 1` we run a ceremony for.
+    min_quorum: usize,
+    /// How long the members have to send their parts.
+    part_deadline: Duration,
+    /// How long the members have to send their acks.
+    ack_deadline: Duration,
+    /// The smallest number of complete parts the key is generated from, if members miss a
+    /// deadline. At least `threshold + 1` parts are always required.
+    min_complete: usize,
+    /// DKG sessions, by session ID.
+    db: Db,
+}
+
+type HandlerError = (StatusCode, String);
+
 #[tokio::main]
 async fn main() {
+    // With arguments, run a command instead of the orchestrator
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        if let Err(e) = cli::run(&args).await {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
-    let db = Db::default();
+    // The beacon rounds are due from `BEACON_GENESIS`, in seconds since the Unix epoch, once per
+    // `BEACON_PERIOD_SECS`, like on the members.
+    let beacon = std::env::var("BEACON_GENESIS")
+        .ok()
+        .map(|genesis| BeaconSchedule {
+            genesis: genesis.parse().expect("Invalid BEACON_GENESIS."),
+            period: env_or("BEACON_PERIOD_SECS", DEFAULT_BEACON_PERIOD_SECS),
+        });
+    let state = AppState {
+        min_quorum: env_or("MIN_QUORUM", DEFAULT_MIN_QUORUM),
+        part_deadline: Duration::from_secs(env_or("PART_DEADLINE_SECS", 5)),
+        ack_deadline: Duration::from_secs(env_or("ACK_DEADLINE_SECS", 5)),
+        min_complete: env_or("MIN_COMPLETE", 0),
+        db: Db::default(),
+    };
+
+    // Refresh the key shares of every finalized session on a schedule, if configured
+    let refresh_interval = env_or("REFRESH_INTERVAL_SECS", 0);
+    if refresh_interval > 0 {
+        tokio::spawn(refresh_periodically(
+            state.clone(),
+            Duration::from_secs(refresh_interval),
+        ));
+    }
+
+    // Run the rounds of the randomness beacon of every finalized session when they are due, if
+    // configured
+    if let Some(schedule) = beacon {
+        tokio::spawn(beacon_periodically(state.clone(), schedule));
+    }
 
     // Compose the routes
     let app = Router::new()
         .route("/init_dkg", post(init_dkg))
         .route("/commit", post(commit))
         .route("/finalize_dkg", post(finalize_dkg))
+        .route("/refresh", post(refresh))
+        .route("/reshare", post(reshare))
+        .route("/pub_key_set", post(pub_key_set))
+        .route("/decrypt", post(decrypt))
+        .route("/decrypt_batch", post(decrypt_batch))
+        .route("/decrypt_for", post(decrypt_for))
+        .route("/decrypt_aead", post(decrypt_aead))
+        .route("/decrypt_labelled", post(decrypt_labelled))
+        .route("/decrypt_envelope", post(decrypt_envelope))
+        .route("/sign", post(sign))
+        .route("/sign_batch", post(sign_batch))
+        .route("/beacon", post(beacon))
         // Add middleware to all routes
         .layer(
             ServiceBuilder::new()
@@ -59,7 +182,7 @@ async fn main() {
                 .layer(TraceLayer::new_for_http())
                 .into_inner(),
         )
-        .with_state(db);
+        .with_state(state);
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3001));
     tracing::debug!("listening on {}", addr);
@@ -69,219 +192,1857 @@ async fn main() {
         .unwrap();
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct StartDkgReq {
+    session_id: usize,
+    /// Any `threshold + 1` of the `num_nodes` members will be able to sign or decrypt.
+    threshold: usize,
+    num_nodes: usize,
+    /// The URLs of the committee members.
+    members: Vec<String>,
+    /// The URLs of the observers, if any.
+    #[serde(default)]
+    observers: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct StartDkgResp {
+    node_ids: Vec<usize>,
+    observer_ids: Vec<usize>,
+    /// The members that didn't send their part before the deadline.
+    absent: BTreeSet<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PubKeyResp {
+    node_id: usize,
+    pk: threshold_crypto::PublicKey,
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 struct InitDkgReq {
-    p1_pk: threshold_crypto::PublicKey,
+    session_id: usize,
+    threshold: usize,
+    num_nodes: usize,
+    pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct InitDkgResp {
-    p0_pk: threshold_crypto::PublicKey,
-    p0_part: Part,
+    part: Part,
 }
 
 #[debug_handler]
-async fn init_dkg(State(db): State<Db>) -> impl IntoResponse {
-    // Create public key with random secret
-    let sk: SecretKey = rand::random();
-    let p1_pk = sk.public_key();
-
-    // Body req to server
-    let req_body = InitDkgReq {
-        p1_pk: p1_pk.clone(),
-    };
+async fn init_dkg(
+    State(state): State<AppState>,
+    Json(req_body): Json<StartDkgReq>,
+) -> Result<Json<StartDkgResp>, HandlerError> {
+    let params = ThresholdParams::new(req_body.threshold, req_body.num_nodes);
+    if params.num_nodes != req_body.members.len() {
+        return Err(bad_request(format!(
+            "Expected {} members, got {}",
+            params.num_nodes,
+            req_body.members.len()
+        )));
+    }
+    params.validate(state.min_quorum).map_err(bad_request)?;
+    if state.db.read().unwrap().contains_key(&req_body.session_id) {
+        return Err(conflict(format!(
+            "Session #{} already exists",
+            req_body.session_id
+        )));
+    }
 
-    // Server returns its public key and part
-    let dkg_init_resp: InitDkgResp = init_dkg_req(SERVER_URL, &req_body)
-        .await
-        .unwrap();
+    // Every member tells us its node ID and encryption key
+    let mut members = BTreeMap::new();
+    let mut pub_keys = BTreeMap::new();
+    for url in req_body.members.iter() {
+        let resp: PubKeyResp = post_req(url, "pub_key", &()).await.map_err(bad_gateway)?;
+        if members.insert(resp.node_id, url.clone()).is_some() {
+            return Err(bad_request(format!("Duplicate node ID #{}", resp.node_id)));
+        }
+        pub_keys.insert(resp.node_id, resp.pk);
+    }
+
+    // Every member creates its `SyncKeyGen` instance and returns its part before the deadline
+    let init_req = InitDkgReq {
+        session_id: req_body.session_id,
+        threshold: params.threshold,
+        num_nodes: params.num_nodes,
+        pub_keys,
+    };
+    let (resps, absent) =
+        broadcast::<_, InitDkgResp>(&members, "init_dkg", &init_req, state.part_deadline).await?;
+    let parts: BTreeMap<usize, Part> = resps.into_iter().map(|(id, r)| (id, r.part)).collect();
+    let min_complete = params.quorum().max(state.min_complete);
+    if parts.len() < min_complete {
+        return Err(bad_gateway(format!(
+            "Only {} members sent their part, {} are needed",
+            parts.len(),
+            min_complete
+        )));
+    }
 
-    // Create a map of public keys
-    let mut map = BTreeMap::new();
-    map.insert(0, dkg_init_resp.p0_pk);
-    map.insert(1, p1_pk.clone());
-
-    // Create SyncKeyGen instance
-    let pub_keys: PubKeyMap<usize, threshold_crypto::PublicKey> = Arc::new(map);
-    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
-    let threshold = 0;
-    
-    let (sync_key_gen, opt_part) =
-        SyncKeyGen::new(1, sk.clone(), pub_keys.clone(), threshold, &mut rng)
-            .unwrap_or_else(|_| panic!("Failed to create `SyncKeyGen` instance for node #{}", 1));
-
-    // Create list of parts and acks, pr
-    let p1_part = opt_part.unwrap().clone();
-    let parts = vec![dkg_init_resp.p0_part, p1_part];
-    let acks = vec![];
+    // Every observer joins the session without a part
+    let mut observers = BTreeMap::new();
+    for url in req_body.observers.iter() {
+        let resp: PubKeyResp = post_req(url, "pub_key", &()).await.map_err(bad_gateway)?;
+        if members.contains_key(&resp.node_id) || observers.contains_key(&resp.node_id) {
+            return Err(bad_request(format!("Duplicate node ID #{}", resp.node_id)));
+        }
+        post_req::<_, ()>(url, "observe", &init_req)
+            .await
+            .map_err(bad_gateway)?;
+        observers.insert(resp.node_id, url.clone());
+    }
 
+    let node_ids = members.keys().cloned().collect();
+    let observer_ids = observers.keys().cloned().collect();
     let session = Session {
-        sk,
-        node: Arc::new(Mutex::new(sync_key_gen)),
+        dealers: members.clone(),
+        members,
+        observers,
+        params,
+        min_parts: params.quorum(),
         parts,
-        acks,
+        acks: vec![],
+        disqualified: vec![],
+        qualified: None,
+        pub_key_set: None,
+        absent: absent.clone(),
+        beacon: vec![],
     };
-    db.write().unwrap().insert(0, session);
+    state
+        .db
+        .write()
+        .unwrap()
+        .insert(req_body.session_id, session);
+
+    Ok(Json(StartDkgResp {
+        node_ids,
+        observer_ids,
+        absent,
+    }))
+}
 
-    Json(())
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SessionReq {
+    session_id: usize,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct CommitReq {
-    p1_part: Part,
-    p1_acks: Vec<Ack>,
+    session_id: usize,
+    parts: BTreeMap<usize, Part>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct CommitResp {
-    p0_acks: Vec<Ack>,
+    acks: Vec<Ack>,
+    complaints: Vec<(usize, Complaint)>,
+    faults: Vec<Fault<usize>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JustifyReq {
+    session_id: usize,
+    accuser_id: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JustifyResp {
+    justification: Option<Justification>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgedComplaint {
+    accuser_id: usize,
+    accused_id: usize,
+    complaint: Complaint,
+    justification: Option<Justification>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgeReq {
+    session_id: usize,
+    complaints: Vec<JudgedComplaint>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgeResp {
+    acks: Vec<Ack>,
+    disqualified: Vec<usize>,
+    faults: Vec<Fault<usize>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct CommitDkgResp {
+    /// The members whose part was discarded after a complaint.
+    disqualified: Vec<usize>,
+    /// The faults detected in the parts and complaints, by reporting node.
+    faults: FaultReports,
+    /// The members that missed a deadline so far.
+    absent: BTreeSet<usize>,
 }
 
 #[debug_handler]
-async fn commit(State(db): State<Db>) -> impl IntoResponse {
-    let session = db.read().unwrap().get(&0).cloned().unwrap();
-
-    let parts = session.parts;
-    let arc_node = session.node.clone();
-    let mut node = arc_node.try_lock().unwrap();
-    let mut p1_acks = vec![];
-
-    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
-    for (id, part) in parts.clone().iter().enumerate() {
-        // We only have 2 participants
-        match node
-            .handle_part(&id, part.clone(), &mut rng)
-            .expect("Failed to handle Part")
-        {
-            PartOutcome::Valid(Some(ack)) => {
-                println!("Node #1 handles Part from node success #{}", id);
-                p1_acks.push(ack)
+async fn commit(
+    State(state): State<AppState>,
+    Json(req_body): Json<SessionReq>,
+) -> Result<Json<CommitDkgResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let commit_req = CommitReq {
+        session_id: req_body.session_id,
+        parts: session.parts,
+    };
+    let (disqualified, faults) =
+        commit_parts(&state, req_body.session_id, "commit", &commit_req).await?;
+    let session = get_session(&state.db, req_body.session_id)?;
+    Ok(Json(CommitDkgResp {
+        disqualified,
+        faults,
+        absent: session.absent,
+    }))
+}
+
+/// Sends the parts to every member on the given route, runs the complaint round and stores the
+/// acks. Members that don't send their acks before the deadline are recorded as absent. Returns
+/// the members whose part was discarded, and the faults reported by each node.
+async fn commit_parts<B: Serialize>(
+    state: &AppState,
+    session_id: usize,
+    route: &str,
+    commit_req: &B,
+) -> Result<(Vec<usize>, FaultReports), HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+
+    // Every member handles all parts, in the same order, and returns its acks before the
+    // deadline
+    let mut acks = vec![];
+    let mut complaints = vec![];
+    let mut faults = FaultReports::new();
+    let (resps, mut absent) = broadcast::<_, CommitResp>(
+        &session.present_members(),
+        route,
+        commit_req,
+        state.ack_deadline,
+    )
+    .await?;
+    for (id, resp) in resps {
+        println!("Node #{} sent {} acks", id, resp.acks.len());
+        acks.extend(resp.acks.into_iter().map(|ack| (id, ack)));
+        complaints.extend(resp.complaints.into_iter().map(|c| (id, c)));
+        faults.entry(id).or_default().extend(resp.faults);
+    }
+    absent.extend(session.absent.iter().cloned());
+    // Observers handle all parts too, but don't send acks or complaints
+    for (id, url) in session.observers.iter() {
+        let resp: CommitResp = post_req(url, route, commit_req)
+            .await
+            .map_err(bad_gateway)?;
+        faults.entry(*id).or_default().extend(resp.faults);
+    }
+
+    // Complaint round: the accused proposers justify their parts, then every member judges
+    // every complaint in the same order.
+    let mut disqualified = vec![];
+    if !complaints.is_empty() {
+        let mut judged_complaints = vec![];
+        for (accuser_id, (named_id, complaint)) in complaints {
+            let justify_req = JustifyReq {
+                session_id,
+                accuser_id,
+            };
+            // The accused proposer is the one whose part the complaint contains, not whoever the
+            // accuser names.
+            let proposer_idx = complaint.proposer_idx() as usize;
+            let (accused_id, url) = match session.dealers.iter().nth(proposer_idx) {
+                Some((id, url)) if *id == named_id => (*id, url),
+                _ => {
+                    println!(
+                        "Node #{} accuses node #{}, but complains about the part of proposer {}",
+                        accuser_id, named_id, proposer_idx
+                    );
+                    continue;
+                }
+            };
+            let justification = justify(url, &justify_req).await.map_err(|e| {
+                bad_gateway(format!(
+                    "Node #{} is unavailable to justify its part: {}",
+                    accused_id, e
+                ))
+            })?;
+            judged_complaints.push(JudgedComplaint {
+                accuser_id,
+                accused_id,
+                complaint,
+                justification,
+            });
+        }
+
+        let judge_req = JudgeReq {
+            session_id,
+            complaints: judged_complaints,
+        };
+        for (id, url) in session.members.iter() {
+            if absent.contains(id) {
+                continue;
             }
-            PartOutcome::Invalid(fault) => {
-                panic!(
-                    "Node #1 handles Part from node #{} and detects a fault: {:?}",
-                    id, fault
-                )
+            let resp: JudgeResp = post_req(url, "judge", &judge_req)
+                .await
+                .map_err(bad_gateway)?;
+            acks.extend(resp.acks.into_iter().map(|ack| (*id, ack)));
+            disqualified = resp.disqualified;
+            faults.entry(*id).or_default().extend(resp.faults);
+        }
+        for (id, url) in session.observers.iter() {
+            let resp: JudgeResp = post_req(url, "judge", &judge_req)
+                .await
+                .map_err(bad_gateway)?;
+            faults.entry(*id).or_default().extend(resp.faults);
+        }
+    }
+
+    state
+        .db
+        .write()
+        .unwrap()
+        .entry(session_id)
+        .and_modify(|session| {
+            session.acks = acks;
+            session.disqualified = disqualified.clone();
+            session.absent = absent;
+        });
+    Ok((disqualified, faults))
+}
+
+/// Asks an accused proposer for its justification, retrying on transport errors: a proposer we
+/// can't reach hasn't refused to justify its part.
+async fn justify(
+    url: &str,
+    justify_req: &JustifyReq,
+) -> Result<Option<Justification>, Box<dyn Error + Send + Sync>> {
+    let mut attempt = 1;
+    loop {
+        match post_req(url, "justify", justify_req).await {
+            Ok(JustifyResp { justification }) => return Ok(justification),
+            Err(e) if attempt < JUSTIFY_ATTEMPTS => {
+                println!(
+                    "Could not reach {} to justify (attempt {}): {}",
+                    url, attempt, e
+                );
+                tokio::time::sleep(Duration::from_secs(attempt)).await;
+                attempt += 1;
             }
-            PartOutcome::Valid(None) => {
-                panic!("We are not an observer, so we should send Ack.")
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct AckReq {
+    session_id: usize,
+    acks: Vec<(usize, Ack)>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct AckResp {
+    qualified: QualifiedSet,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct FinalizeReq {
+    session_id: usize,
+    qualified: QualifiedSet,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct FinalizeResp {
+    pub_key_set: PublicKeySet,
+    sig_share: Option<SignatureShare>,
+    faults: Vec<Fault<usize>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct FinalizeDkgResp {
+    pub_key_set: PublicKeySet,
+    params: ThresholdParams,
+    /// The members whose part didn't contribute to the key.
+    disqualified: Vec<usize>,
+    /// The members whose part makes up the key.
+    qualified: Vec<usize>,
+    /// All faults detected in the key generation, by reporting node. Faulty members should be
+    /// excluded from the next key generation.
+    faults: FaultReports,
+    /// The members that missed a deadline. They hold no share of the key.
+    absent: BTreeSet<usize>,
+    /// The number of signature or decryption shares needed: `threshold + 1`.
+    quorum: usize,
+    /// The members whose confirmation signature share doesn't match their public key share.
+    rejected: Vec<usize>,
+    is_success: bool,
+}
+
+async fn finalize_dkg(
+    State(state): State<AppState>,
+    Json(req_body): Json<SessionReq>,
+) -> Result<Json<FinalizeDkgResp>, HandlerError> {
+    let resp = finalize(&state, req_body.session_id).await?;
+    audit_transcripts(&state, req_body.session_id).await?;
+    Ok(Json(resp))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct TranscriptResp {
+    transcript: Transcript,
+}
+
+/// Checks that the transcript of every member and observer reproduces the key of a new session,
+/// so that the key provably came from the parts and acks they handled.
+async fn audit_transcripts(state: &AppState, session_id: usize) -> Result<(), HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+    let (qualified, pub_key_set) = match (session.qualified, session.pub_key_set) {
+        (Some(qualified), Some(pub_key_set)) => (qualified, pub_key_set),
+        _ => {
+            return Err(bad_request(format!(
+                "Session #{} has no key yet",
+                session_id
+            )))
+        }
+    };
+    let transcript_req = SessionReq { session_id };
+    let members = session.present_members();
+    for (id, url) in members.iter().chain(session.observers.iter()) {
+        let resp: TranscriptResp = post_req(url, "transcript", &transcript_req)
+            .await
+            .map_err(bad_gateway)?;
+        let verified = resp.transcript.verify(session.params, Some(&qualified));
+        if verified.as_ref() != Ok(&pub_key_set) {
+            return Err(bad_gateway(format!(
+                "The transcript of node #{} doesn't match the key: {:?}",
+                id,
+                verified.err()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Agrees on the qualified parts with every member, and checks the key they generate from them.
+async fn finalize(state: &AppState, session_id: usize) -> Result<FinalizeDkgResp, HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+    let params = session.params;
+
+    // Every member handles all acks and reports the parts that are complete from its point of
+    // view before the deadline. The members may handle acks in any order, so they agree on the
+    // reported intersection.
+    let ack_req = AckReq {
+        session_id,
+        acks: session.acks.clone(),
+    };
+    let (resps, mut absent) = broadcast::<_, AckResp>(
+        &session.present_members(),
+        "ack",
+        &ack_req,
+        state.ack_deadline,
+    )
+    .await?;
+    absent.extend(session.absent.iter().cloned());
+    let mut qualified: Option<QualifiedSet> = None;
+    for resp in resps.into_values() {
+        qualified = Some(match qualified {
+            Some(qs) => qs.intersection(&resp.qualified),
+            None => resp.qualified,
+        });
+    }
+    // Observers handle all acks too, and follow the members' agreement
+    for url in session.observers.values() {
+        post_req::<_, AckResp>(url, "ack", &ack_req)
+            .await
+            .map_err(bad_gateway)?;
+    }
+    let qualified = qualified.ok_or_else(|| bad_gateway("No member sent its acks in time"))?;
+    let min_complete = session.min_complete(state);
+    if qualified.len() < min_complete {
+        return Err(bad_gateway(format!(
+            "Only {} parts are complete, {} are needed",
+            qualified.len(),
+            min_complete
+        )));
+    }
+
+    // Every member generates its key share from the agreed parts and signs the confirmation
+    // of the session and key
+    let finalize_req = FinalizeReq {
+        session_id,
+        qualified: qualified.clone(),
+    };
+    let mut pub_key_set: Option<PublicKeySet> = None;
+    let mut sig_shares: BTreeMap<usize, SignatureShare> = BTreeMap::new();
+    let mut faults = FaultReports::new();
+    for (id, url) in session.members.iter() {
+        if absent.contains(id) {
+            continue;
+        }
+        let resp: FinalizeResp = post_req(url, "finalize_dkg", &finalize_req)
+            .await
+            .map_err(bad_gateway)?;
+        // All nodes must agree on the public keys and public key shares.
+        let pks = pub_key_set.get_or_insert_with(|| resp.pub_key_set.clone());
+        if *pks != resp.pub_key_set {
+            return Err(bad_gateway(format!(
+                "Node #{} generated a different public key set",
+                id
+            )));
+        }
+        let sig_share = resp
+            .sig_share
+            .ok_or_else(|| bad_gateway(format!("Node #{} has no key share", id)))?;
+        sig_shares.insert(*id, sig_share);
+        faults.insert(*id, resp.faults);
+    }
+    let pub_key_set = pub_key_set.ok_or_else(|| bad_request("The committee is empty"))?;
+    // Every observer generates the same public key set, without a key share
+    for (id, url) in session.observers.iter() {
+        let resp: FinalizeResp = post_req(url, "finalize_dkg", &finalize_req)
+            .await
+            .map_err(bad_gateway)?;
+        if resp.pub_key_set != pub_key_set || resp.sig_share.is_some() {
+            return Err(bad_gateway(format!(
+                "Observer #{} generated a different key",
+                id
+            )));
+        }
+        faults.insert(*id, resp.faults);
+    }
+    if pub_key_set.threshold() != params.threshold {
+        return Err(bad_gateway(format!(
+            "Expected a key with threshold {}, got {}",
+            params.threshold,
+            pub_key_set.threshold()
+        )));
+    }
+
+    let msg = dkg::dkg_confirmation(session_id as u64, &pub_key_set).map_err(internal_error)?;
+
+    // Node IDs are not share indices: the share index is the position in the committee. Shares
+    // that don't match their public key share are reported and left out.
+    let mut indexed_shares: Vec<(usize, &SignatureShare)> = vec![];
+    let mut rejected = vec![];
+    for (idx, id) in session.members.keys().enumerate() {
+        if let Some(sig_share) = sig_shares.get(id) {
+            let pk_share = pub_key_set.public_key_share(idx);
+            if SigningContext::DkgConfirm.verify_share(&pk_share, sig_share, &msg) {
+                indexed_shares.push((idx, sig_share));
+            } else {
+                println!("Node #{} sent an invalid signature share", id);
+                rejected.push(*id);
             }
         }
     }
 
-    // Send req to server
-    let req_body = CommitReq {
-        p1_part: parts[1].clone(),
-        p1_acks: p1_acks.clone(),
+    // Fewer than `threshold + 1` shares must not produce a signature...
+    let below_quorum = indexed_shares.iter().cloned().take(params.threshold);
+    let below_quorum_fails = pub_key_set.combine_signatures(below_quorum).is_err();
+    // ...while any `threshold + 1` shares produce the one valid signature.
+    let quorum = indexed_shares.iter().cloned().take(params.quorum());
+    let quorum_signs = match pub_key_set.combine_signatures(quorum) {
+        Ok(sig) => SigningContext::DkgConfirm.verify(&pub_key_set.public_key(), &sig, &msg),
+        Err(e) => {
+            println!("Failed to combine signature shares: {:?}", e);
+            false
+        }
     };
-    let commit_resp: CommitResp = commit_req(SERVER_URL, &req_body)
-        .await
-        .unwrap();
+    let is_success = rejected.is_empty() && below_quorum_fails && quorum_signs;
+    println!("is_success: {:?}", is_success);
 
-    let p0_acks = commit_resp.p0_acks;
-    let mut acks = vec![];
-    // Acks order follows the order of parts, we have 2 acks for each part
-    for ack in p0_acks {
-        acks.push(ack);
+    state
+        .db
+        .write()
+        .unwrap()
+        .entry(session_id)
+        .and_modify(|session| {
+            session.qualified = Some(qualified.clone());
+            session.pub_key_set = Some(pub_key_set.clone());
+            session.absent = absent.clone();
+        });
+
+    Ok(FinalizeDkgResp {
+        pub_key_set,
+        params,
+        disqualified: session.disqualified,
+        qualified: session
+            .dealers
+            .keys()
+            .enumerate()
+            .filter(|(idx, _)| qualified.contains(*idx as u64))
+            .map(|(_, id)| *id)
+            .collect(),
+        faults,
+        absent,
+        quorum: params.quorum(),
+        rejected,
+        is_success,
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitRefreshResp {
+    part: Option<RefreshPart>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct CommitRefreshReq {
+    session_id: usize,
+    parts: BTreeMap<usize, RefreshPart>,
+}
+
+/// Re-randomizes the key shares of a finalized session. The master public key stays the same,
+/// but shares from before the refresh don't combine with shares from after it.
+async fn refresh(
+    State(state): State<AppState>,
+    Json(req_body): Json<SessionReq>,
+) -> Result<Json<FinalizeDkgResp>, HandlerError> {
+    refresh_shares(&state, req_body.session_id).await.map(Json)
+}
+
+/// Runs a share refresh through the same phases as a new key.
+async fn refresh_shares(
+    state: &AppState,
+    session_id: usize,
+) -> Result<FinalizeDkgResp, HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+    let old_pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", session_id)))?;
+
+    // Every member that holds a key share deals a refresh part for it before the deadline
+    let init_req = SessionReq { session_id };
+    let (resps, absent) = broadcast::<_, InitRefreshResp>(
+        &session.present_members(),
+        "init_refresh",
+        &init_req,
+        state.part_deadline,
+    )
+    .await?;
+    let mut parts = BTreeMap::new();
+    for (id, resp) in resps {
+        let part = resp
+            .part
+            .ok_or_else(|| bad_gateway(format!("Node #{} has no key share", id)))?;
+        parts.insert(id, part);
+    }
+    state
+        .db
+        .write()
+        .unwrap()
+        .entry(session_id)
+        .and_modify(|session| session.absent.extend(absent));
+    // Every observer follows the refresh without a part
+    for url in session.observers.values() {
+        post_req::<_, InitRefreshResp>(url, "init_refresh", &init_req)
+            .await
+            .map_err(bad_gateway)?;
     }
 
-    for ack in p1_acks {
-        acks.push(ack);
+    let commit_req = CommitRefreshReq { session_id, parts };
+    commit_parts(state, session_id, "commit_refresh", &commit_req).await?;
+    let resp = finalize(state, session_id).await?;
+    if resp.pub_key_set.public_key() != old_pub_key_set.public_key() {
+        return Err(bad_gateway("The refresh changed the master public key"));
     }
+    Ok(resp)
+}
 
-    let updated_session = Session {
-        sk: session.sk,
-        node: session.node,
-        parts,
-        acks,
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct ReshareReq {
+    /// The session of the new key.
+    session_id: usize,
+    /// The finalized session whose key is reshared.
+    old_session_id: usize,
+    /// Any `threshold + 1` of the `num_nodes` new members will be able to sign or decrypt.
+    threshold: usize,
+    num_nodes: usize,
+    /// The URLs of the new committee members.
+    members: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct InitReshareReq {
+    session_id: usize,
+    old_session_id: usize,
+    threshold: usize,
+    num_nodes: usize,
+    pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
+    old_pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
+    pub_key_set: PublicKeySet,
+    observers: BTreeSet<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitReshareResp {
+    part: Option<RefreshPart>,
+}
+
+/// Reshares the key of a finalized session to a new committee, with a possibly different
+/// threshold. The new key has the same master public key, so existing ciphertexts and
+/// verifiers keep working.
+#[debug_handler]
+async fn reshare(
+    State(state): State<AppState>,
+    Json(req_body): Json<ReshareReq>,
+) -> Result<Json<FinalizeDkgResp>, HandlerError> {
+    if req_body.session_id == req_body.old_session_id {
+        return Err(bad_request("The reshared key needs a new session ID"));
+    }
+    if state.db.read().unwrap().contains_key(&req_body.session_id) {
+        return Err(conflict(format!(
+            "Session #{} already exists",
+            req_body.session_id
+        )));
+    }
+    let old_session = get_session(&state.db, req_body.old_session_id)?;
+    let old_pub_key_set = old_session.pub_key_set.ok_or_else(|| {
+        bad_request(format!(
+            "Session #{} has no key yet",
+            req_body.old_session_id
+        ))
+    })?;
+    let params = ThresholdParams::new(req_body.threshold, req_body.num_nodes);
+    if params.num_nodes != req_body.members.len() {
+        return Err(bad_request(format!(
+            "Expected {} members, got {}",
+            params.num_nodes,
+            req_body.members.len()
+        )));
+    }
+    params.validate(state.min_quorum).map_err(bad_request)?;
+
+    // Every node of both committees tells us its node ID and encryption key
+    let mut members = BTreeMap::new();
+    let mut pub_keys = BTreeMap::new();
+    for url in req_body.members.iter() {
+        let resp: PubKeyResp = post_req(url, "pub_key", &()).await.map_err(bad_gateway)?;
+        if members.insert(resp.node_id, url.clone()).is_some() {
+            return Err(bad_request(format!("Duplicate node ID #{}", resp.node_id)));
+        }
+        pub_keys.insert(resp.node_id, resp.pk);
+    }
+    let mut old_pub_keys = BTreeMap::new();
+    for (id, url) in old_session.members.iter() {
+        let resp: PubKeyResp = post_req(url, "pub_key", &()).await.map_err(bad_gateway)?;
+        old_pub_keys.insert(*id, resp.pk);
+    }
+    // The observers of the current key keep observing the reshared key, unless they join the
+    // new committee.
+    let observers: BTreeMap<usize, String> = old_session
+        .observers
+        .into_iter()
+        .filter(|(id, _)| !members.contains_key(id))
+        .collect();
+
+    // Every node creates its resharing instance, and the current share holders deal their parts
+    let init_req = InitReshareReq {
+        session_id: req_body.session_id,
+        old_session_id: req_body.old_session_id,
+        threshold: params.threshold,
+        num_nodes: params.num_nodes,
+        pub_keys,
+        old_pub_keys,
+        pub_key_set: old_pub_key_set.clone(),
+        observers: observers.keys().cloned().collect(),
+    };
+    let mut all_nodes = old_session.members.clone();
+    all_nodes.extend(members.clone());
+    all_nodes.extend(observers.clone());
+    let mut parts = BTreeMap::new();
+    for (id, url) in all_nodes.iter() {
+        let resp: InitReshareResp = post_req(url, "init_reshare", &init_req)
+            .await
+            .map_err(bad_gateway)?;
+        parts.extend(resp.part.map(|part| (*id, part)));
+    }
+
+    let session = Session {
+        members,
+        dealers: old_session.members,
+        observers,
+        params,
+        min_parts: old_pub_key_set.threshold() + 1,
+        parts: BTreeMap::new(),
+        acks: vec![],
+        disqualified: vec![],
+        qualified: None,
+        pub_key_set: None,
+        absent: BTreeSet::new(),
+        beacon: vec![],
     };
-    db.write().unwrap().insert(0, updated_session);
-    Json(())
+    state
+        .db
+        .write()
+        .unwrap()
+        .insert(req_body.session_id, session);
+
+    let session_id = req_body.session_id;
+    let commit_req = CommitRefreshReq { session_id, parts };
+    commit_parts(&state, session_id, "commit_refresh", &commit_req).await?;
+    let resp = finalize(&state, session_id).await?;
+    if resp.pub_key_set.public_key() != old_pub_key_set.public_key() {
+        return Err(bad_gateway("The resharing changed the master public key"));
+    }
+    Ok(Json(resp))
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-struct FinalizeReq {
-    sig_share_1: SignatureShare,
-    signed_msg_1: String,
+struct PubKeySetResp {
+    pub_key_set: PublicKeySet,
+}
+
+/// Returns the public key set of a finalized session, to encrypt to its master public key.
+async fn pub_key_set(
+    State(state): State<AppState>,
+    Json(req_body): Json<SessionReq>,
+) -> Result<Json<PubKeySetResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    Ok(Json(PubKeySetResp { pub_key_set }))
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
-struct FinalizeResp {
-    is_success: bool,
+struct DecryptReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: Ciphertext,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareReq {
+    session_id: usize,
+    ciphertext: Ciphertext,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareResp {
+    node_idx: usize,
+    dec_share: DecryptionShare,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptResp {
+    plaintext: Vec<u8>,
+    /// The members whose decryption share was combined.
+    contributors: Vec<usize>,
+    /// The members that sent an invalid decryption share.
+    rejected: Vec<usize>,
+}
+
+/// Collects decryption shares from the members until `threshold + 1` of them are valid, and
+/// combines them. Unreachable members and invalid shares are skipped.
+async fn decrypt(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptReq>,
+) -> Result<Json<DecryptResp>, HandlerError> {
+    let (pub_key_set, shares) =
+        collect_decryption_shares(&state, req_body.session_id, &req_body.ciphertext).await?;
+    let plaintext = pub_key_set
+        .decrypt(&shares.by_idx, &req_body.ciphertext)
+        .map_err(|e| bad_gateway(format!("Failed to combine decryption shares: {:?}", e)))?;
+    Ok(Json(DecryptResp {
+        plaintext,
+        contributors: shares.contributors,
+        rejected: shares.rejected,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptBatchReq {
+    /// The session of the key the ciphertexts were encrypted to.
+    session_id: usize,
+    ciphertexts: Vec<Ciphertext>,
 }
 
-async fn finalize_dkg(State(db): State<Db>) -> impl IntoResponse {
-    let session = db.read().unwrap().get(&0).cloned().unwrap();
-    let arc_node = session.node.clone();
-    let mut node = arc_node.try_lock().unwrap();
-    let acks = session.acks;
-
-    // we handle all the `Ack`s.
-    for ack in acks {
-        for id in 0..1 {
-            match node
-                .handle_ack(&id, ack.clone())
-                .expect("Failed to handle Ack")
-            {
-                AckOutcome::Valid => (),
-                AckOutcome::Invalid(fault) => panic!("Invalid Ack: {:?}", fault),
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareBatchResp {
+    node_idx: usize,
+    dec_shares: Vec<Result<DecryptionShare, String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptBatchResp {
+    /// The result for each ciphertext, in the same order.
+    results: Vec<Result<DecryptResp, String>>,
+}
+
+/// Decrypts up to `MAX_BATCH_SIZE` ciphertexts with one request per member: each member returns
+/// its decryption shares of all ciphertexts that don't have `threshold + 1` valid shares yet. A
+/// ciphertext that can't be decrypted doesn't fail the other ones.
+async fn decrypt_batch(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptBatchReq>,
+) -> Result<Json<DecryptBatchResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    if req_body.ciphertexts.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(format!(
+            "At most {} ciphertexts per batch",
+            MAX_BATCH_SIZE
+        )));
+    }
+    // Checking ciphertexts and shares takes pairings: it runs off the async runtime.
+    let ciphertexts = req_body.ciphertexts;
+    let (ciphertexts, is_valid) = tokio::task::spawn_blocking(move || {
+        let is_valid: Vec<bool> = ciphertexts.iter().map(|ct| ct.verify()).collect();
+        (ciphertexts, is_valid)
+    })
+    .await
+    .map_err(internal_error)?;
+    let mut all_shares: Vec<CollectedShares<DecryptionShare>> =
+        ciphertexts.iter().map(|_| CollectedShares::new()).collect();
+
+    // Node IDs are not share indices: the share index is the position in the committee.
+    for (idx, (id, url)) in session.members.iter().enumerate() {
+        let pending: Vec<usize> = (0..ciphertexts.len())
+            .filter(|&i| is_valid[i])
+            .filter(|&i| all_shares[i].by_idx.len() <= pub_key_set.threshold())
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+        if session.absent.contains(id) {
+            continue;
+        }
+        let share_req = DecryptBatchReq {
+            session_id: req_body.session_id,
+            ciphertexts: pending.iter().map(|&i| ciphertexts[i].clone()).collect(),
+        };
+        let resp: DecryptShareBatchResp =
+            match post_req(url, "decrypt_share_batch", &share_req).await {
+                Ok(resp) => resp,
+                Err(e) => {
+                    println!("Node #{} sent no decryption shares: {}", id, e);
+                    continue;
+                }
+            };
+        if resp.node_idx != idx || resp.dec_shares.len() != pending.len() {
+            println!("Node #{} sent an invalid batch of decryption shares", id);
+            for &i in &pending {
+                all_shares[i].rejected.push(*id);
+            }
+            continue;
+        }
+        let pub_key_share = pub_key_set.public_key_share(idx);
+        let pending_cts = share_req.ciphertexts;
+        let checked = tokio::task::spawn_blocking(move || {
+            let results = resp.dec_shares.into_iter().zip(&pending_cts);
+            let check = |(result, ct): (Result<DecryptionShare, String>, &Ciphertext)| {
+                result.map(|dec_share| {
+                    let is_valid = pub_key_share.verify_decryption_share(&dec_share, ct);
+                    (dec_share, is_valid)
+                })
+            };
+            results.map(check).collect::<Vec<_>>()
+        })
+        .await
+        .map_err(internal_error)?;
+        for (&i, result) in pending.iter().zip(checked) {
+            let shares = &mut all_shares[i];
+            match result {
+                Ok((dec_share, true)) => {
+                    shares.by_idx.insert(idx, dec_share);
+                    shares.contributors.push(*id);
+                }
+                Ok((_, false)) => shares.rejected.push(*id),
+                Err(e) => println!("Node #{} sent no decryption share #{}: {}", id, i, e),
             }
         }
     }
 
-    let pub_key_set = node
-        .generate()
-        .expect("Failed to create `PublicKeySet` from node #1")
-        .0;
-    assert!(node.is_ready());
+    let results = ciphertexts
+        .iter()
+        .zip(is_valid)
+        .zip(all_shares)
+        .map(|((ciphertext, is_valid), shares)| {
+            if !is_valid {
+                return Err("Invalid ciphertext".to_string());
+            }
+            if shares.by_idx.len() <= pub_key_set.threshold() {
+                return Err(format!(
+                    "Only {} valid decryption shares, {} are needed",
+                    shares.by_idx.len(),
+                    pub_key_set.threshold() + 1
+                ));
+            }
+            let plaintext = pub_key_set
+                .decrypt(&shares.by_idx, ciphertext)
+                .map_err(|e| format!("Failed to combine decryption shares: {:?}", e))?;
+            Ok(DecryptResp {
+                plaintext,
+                contributors: shares.contributors,
+                rejected: shares.rejected,
+            })
+        })
+        .collect();
+    Ok(Json(DecryptBatchResp { results }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptForReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: Ciphertext,
+    /// The public key of the recipient, the only one who can combine the shares.
+    recipient: threshold_crypto::PublicKey,
+    /// The recipient's signature of `dkg::decrypt_for_request`, which the members check.
+    recipient_sig: Signature,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareForResp {
+    node_idx: usize,
+    enc_share: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptForResp {
+    /// The decryption shares encrypted to the recipient, by share index.
+    enc_shares: BTreeMap<usize, Vec<u8>>,
+    /// The members that sent an encrypted share.
+    contributors: Vec<usize>,
+}
+
+/// Collects the decryption shares of every available member, encrypted to the recipient. We
+/// can't verify or combine them: the recipient does, with `dkg::decrypt_with_encrypted_shares`,
+/// so we never learn the plaintext. Since some shares may turn out invalid, we don't stop at
+/// `threshold + 1` of them.
+async fn decrypt_for(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptForReq>,
+) -> Result<Json<DecryptForResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    if !req_body.ciphertext.verify() {
+        return Err(bad_request("Invalid ciphertext"));
+    }
+
+    let shares = collect_shares::<_, DecryptShareForResp, _>(
+        &session,
+        pub_key_set,
+        "decrypt_share_for",
+        &req_body,
+        session.members.len(),
+        |_, _| true,
+    )
+    .await?;
+    Ok(Json(DecryptForResp {
+        enc_shares: shares.by_idx,
+        contributors: shares.contributors,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptAeadReq {
+    /// The session of the key the data key was encrypted to.
+    session_id: usize,
+    ciphertext: HybridCiphertext,
+    /// The associated data the ciphertext was encrypted with.
+    #[serde(default)]
+    associated_data: Vec<u8>,
+}
+
+/// Decrypts a hybrid ciphertext: the members decrypt the data key like in `/decrypt`, and the
+/// payload is decrypted and authenticated with it, together with the associated data.
+async fn decrypt_aead(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptAeadReq>,
+) -> Result<Json<DecryptResp>, HandlerError> {
+    let key_ct = req_body.ciphertext.key_ciphertext();
+    let (pub_key_set, shares) =
+        collect_decryption_shares(&state, req_body.session_id, key_ct).await?;
+    let plaintext = req_body
+        .ciphertext
+        .decrypt(&pub_key_set, &shares.by_idx, &req_body.associated_data)
+        .map_err(bad_request)?;
+    Ok(Json(DecryptResp {
+        plaintext,
+        contributors: shares.contributors,
+        rejected: shares.rejected,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptLabelledReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: LabelledCiphertext,
+    /// The label the ciphertext is expected to be bound to.
+    label: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareLabelledResp {
+    node_idx: usize,
+    dec_share: LabelledDecryptionShare,
+}
+
+/// Collects decryption shares of a labelled ciphertext from the members until `threshold + 1`
+/// of them have a valid proof, and combines them. The members refuse to issue shares unless the
+/// ciphertext is bound to the expected label.
+async fn decrypt_labelled(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptLabelledReq>,
+) -> Result<Json<DecryptResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    if req_body.ciphertext.label() != req_body.label.as_slice() {
+        return Err(bad_request("The ciphertext label doesn't match"));
+    }
+    if !req_body.ciphertext.verify() {
+        return Err(bad_request("Invalid ciphertext"));
+    }
+
+    let ciphertext = &req_body.ciphertext;
+    let shares = collect_shares::<_, DecryptShareLabelledResp, _>(
+        &session,
+        pub_key_set,
+        "decrypt_share_labelled",
+        &req_body,
+        pub_key_set.threshold() + 1,
+        |idx, dec_share| ciphertext.verify_decryption_share(pub_key_set, idx, dec_share),
+    )
+    .await?;
+
+    let dec_shares = shares.by_idx.iter().map(|(idx, share)| (*idx, share));
+    let plaintext = ciphertext
+        .decrypt(pub_key_set, dec_shares)
+        .map_err(bad_gateway)?;
+    Ok(Json(DecryptResp {
+        plaintext,
+        contributors: shares.contributors,
+        rejected: shares.rejected,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptEnvelopeReq {
+    envelope: Envelope,
+}
 
-    let (pks, opt_sks) = node.generate().unwrap_or_else(|_| {
-        panic!("Failed to create `PublicKeySet` and `SecretKeyShare` for node #1")
-    });
-    assert_eq!(pks, pub_key_set); // All nodes now know the public keys and public key shares.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareEnvelopeReq {
+    session_id: usize,
+    envelope: Envelope,
+}
 
-    let sks_1 = opt_sks.expect("Not an observer node: We receive a secret key share.");
-    let msg = "Sign this";
-    let sig_share_1 = sks_1.sign(msg);
+/// Decrypts an envelope with the key it was encrypted to, found by its fingerprint. If several
+/// sessions share that key, e.g. after resharing, the latest one is used, and the members are told
+/// to use their key share of that session.
+async fn decrypt_envelope(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptEnvelopeReq>,
+) -> Result<Json<DecryptResp>, HandlerError> {
+    let envelope = &req_body.envelope;
+    // Fails unless we understand the envelope's version and scheme.
+    let key_ct = envelope.key_ciphertext().map_err(bad_request)?;
+    let session_id = state
+        .db
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(_, session)| {
+            let pub_key_set = session.pub_key_set.as_ref();
+            pub_key_set.and_then(|pks| envelope::fingerprint(pks).ok())
+                == Some(*envelope.key_fingerprint())
+        })
+        .map(|(session_id, _)| *session_id)
+        .max()
+        .ok_or_else(|| bad_request("Unknown key fingerprint"))?;
+    let share_req = DecryptShareEnvelopeReq {
+        session_id,
+        envelope: envelope.clone(),
+    };
+    let (pub_key_set, shares) = collect_decryption_shares_via(
+        &state,
+        session_id,
+        &key_ct,
+        "decrypt_share_envelope",
+        &share_req,
+    )
+    .await?;
+    let plaintext = envelope
+        .open(&pub_key_set, &shares.by_idx)
+        .map_err(bad_request)?;
+    Ok(Json(DecryptResp {
+        plaintext,
+        contributors: shares.contributors,
+        rejected: shares.rejected,
+    }))
+}
 
-    // Send req to server
-    let req_body = FinalizeReq {
-        sig_share_1,
-        signed_msg_1: msg.to_string(),
+/// Asks the members for their decryption shares of the ciphertext until `threshold + 1` of them
+/// are valid. Returns the session's public key set and the valid shares.
+async fn collect_decryption_shares(
+    state: &AppState,
+    session_id: usize,
+    ciphertext: &Ciphertext,
+) -> Result<(PublicKeySet, CollectedShares<DecryptionShare>), HandlerError> {
+    let share_req = DecryptShareReq {
+        session_id,
+        ciphertext: ciphertext.clone(),
     };
-    let is_success = finalize_dkg_req(SERVER_URL, &req_body)
+    collect_decryption_shares_via(state, session_id, ciphertext, "decrypt_share", &share_req).await
+}
+
+/// Like `collect_decryption_shares`, but sends `share_req` to the given route of the members.
+async fn collect_decryption_shares_via<B: Serialize>(
+    state: &AppState,
+    session_id: usize,
+    ciphertext: &Ciphertext,
+    route: &str,
+    share_req: &B,
+) -> Result<(PublicKeySet, CollectedShares<DecryptionShare>), HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .clone()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", session_id)))?;
+    if !ciphertext.verify() {
+        return Err(bad_request("Invalid ciphertext"));
+    }
+
+    let shares = collect_shares::<_, DecryptShareResp, _>(
+        &session,
+        &pub_key_set,
+        route,
+        share_req,
+        pub_key_set.threshold() + 1,
+        |idx, dec_share| {
+            let pk_share = pub_key_set.public_key_share(idx);
+            pk_share.verify_decryption_share(dec_share, ciphertext)
+        },
+    )
+    .await?;
+    Ok((pub_key_set, shares))
+}
+
+/// A member's response with its share of a decryption or signature.
+trait ShareResp: DeserializeOwned {
+    type Share;
+
+    /// Returns the share index the member claims, and its share.
+    fn into_share(self) -> (usize, Self::Share);
+}
+
+impl ShareResp for DecryptShareResp {
+    type Share = DecryptionShare;
+
+    fn into_share(self) -> (usize, DecryptionShare) {
+        (self.node_idx, self.dec_share)
+    }
+}
+
+impl ShareResp for DecryptShareForResp {
+    type Share = Vec<u8>;
+
+    fn into_share(self) -> (usize, Vec<u8>) {
+        (self.node_idx, self.enc_share)
+    }
+}
+
+impl ShareResp for DecryptShareLabelledResp {
+    type Share = LabelledDecryptionShare;
+
+    fn into_share(self) -> (usize, LabelledDecryptionShare) {
+        (self.node_idx, self.dec_share)
+    }
+}
+
+impl ShareResp for SignShareResp {
+    type Share = SignatureShare;
+
+    fn into_share(self) -> (usize, SignatureShare) {
+        (self.node_idx, self.sig_share)
+    }
+}
+
+impl ShareResp for BeaconShareResp {
+    type Share = SignatureShare;
+
+    fn into_share(self) -> (usize, SignatureShare) {
+        (self.node_idx, self.sig_share)
+    }
+}
+
+/// The valid decryption or signature shares of a ciphertext or message.
+struct CollectedShares<S> {
+    /// The valid shares, by share index.
+    by_idx: BTreeMap<usize, S>,
+    /// The members whose share is valid.
+    contributors: Vec<usize>,
+    /// The members that sent an invalid share.
+    rejected: Vec<usize>,
+}
+
+impl<S> CollectedShares<S> {
+    fn new() -> Self {
+        CollectedShares {
+            by_idx: BTreeMap::new(),
+            contributors: vec![],
+            rejected: vec![],
+        }
+    }
+}
+
+/// Sends `share_req` to the given route of the members until `wanted` of them returned a share
+/// that passes `is_valid`, given its share index. Unreachable members are skipped, and members
+/// that send an invalid share or the wrong share index are rejected. Fails unless more than
+/// `threshold` shares are valid.
+async fn collect_shares<B, R, F>(
+    session: &Session,
+    pub_key_set: &PublicKeySet,
+    route: &str,
+    share_req: &B,
+    wanted: usize,
+    is_valid: F,
+) -> Result<CollectedShares<R::Share>, HandlerError>
+where
+    B: Serialize,
+    R: ShareResp,
+    F: Fn(usize, &R::Share) -> bool,
+{
+    let mut shares = CollectedShares::new();
+    // Node IDs are not share indices: the share index is the position in the committee.
+    for (idx, (id, url)) in session.members.iter().enumerate() {
+        if shares.by_idx.len() >= wanted {
+            break;
+        }
+        if session.absent.contains(id) {
+            continue;
+        }
+        let (node_idx, share) = match post_req::<_, R>(url, route, share_req).await {
+            Ok(resp) => resp.into_share(),
+            Err(e) => {
+                println!("Node #{} sent no {} share: {}", id, route, e);
+                continue;
+            }
+        };
+        if node_idx == idx && is_valid(idx, &share) {
+            shares.by_idx.insert(idx, share);
+            shares.contributors.push(*id);
+        } else {
+            println!("Node #{} sent an invalid {} share", id, route);
+            shares.rejected.push(*id);
+        }
+    }
+
+    if shares.by_idx.len() <= pub_key_set.threshold() {
+        return Err(bad_gateway(format!(
+            "Only {} valid {} shares, {} are needed",
+            shares.by_idx.len(),
+            route,
+            pub_key_set.threshold() + 1
+        )));
+    }
+    Ok(shares)
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignReq {
+    /// The session of the key to sign with.
+    session_id: usize,
+    /// The context the signature is made in, e.g. `attestation`.
+    context: SigningContext,
+    /// The message to sign, base64-encoded.
+    #[serde(with = "base64_bytes")]
+    msg: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareResp {
+    node_idx: usize,
+    sig_share: SignatureShare,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignResp {
+    signature: Signature,
+    /// The members whose signature share was combined.
+    contributors: Vec<usize>,
+    /// The members that sent an invalid signature share.
+    rejected: Vec<usize>,
+}
+
+/// Collects signature shares of the message in an open context from the members until
+/// `threshold + 1` of them match their public key share, and combines them into the signature of
+/// the session's master key. Unreachable members and invalid shares are skipped.
+async fn sign(
+    State(state): State<AppState>,
+    Json(req_body): Json<SignReq>,
+) -> Result<Json<SignResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let context = open_context(req_body.context)?;
+
+    let shares = collect_shares::<_, SignShareResp, _>(
+        &session,
+        pub_key_set,
+        "sign_share",
+        &req_body,
+        pub_key_set.threshold() + 1,
+        |idx, sig_share| {
+            let pk_share = pub_key_set.public_key_share(idx);
+            context.verify_share(&pk_share, sig_share, &req_body.msg)
+        },
+    )
+    .await?;
+    let signature = pub_key_set
+        .combine_signatures(&shares.by_idx)
+        .map_err(|e| bad_gateway(format!("Failed to combine signature shares: {:?}", e)))?;
+    if !context.verify(&pub_key_set.public_key(), &signature, &req_body.msg) {
+        return Err(bad_gateway("The combined signature is invalid"));
+    }
+    Ok(Json(SignResp {
+        signature,
+        contributors: shares.contributors,
+        rejected: shares.rejected,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignBatchReq {
+    /// The session of the key to sign with.
+    session_id: usize,
+    /// The context the signature is made in, e.g. `attestation`.
+    context: SigningContext,
+    /// The messages to sign, base64-encoded.
+    #[serde(with = "base64_bytes::vec")]
+    msgs: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareBatchResp {
+    node_idx: usize,
+    sig_shares: Vec<SignatureShare>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignBatchResp {
+    /// The result for each message, in the same order.
+    results: Vec<Result<SignResp, String>>,
+}
+
+/// Signs many messages with one request per member: each member returns its signature shares of
+/// all messages that don't have `threshold + 1` valid shares yet. A message that can't be signed
+/// doesn't fail the other ones.
+async fn sign_batch(
+    State(state): State<AppState>,
+    Json(req_body): Json<SignBatchReq>,
+) -> Result<Json<SignBatchResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let context = open_context(req_body.context)?;
+    if req_body.msgs.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(format!(
+            "At most {} messages per batch",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let msgs = req_body.msgs;
+    let mut all_shares: Vec<CollectedShares<SignatureShare>> =
+        msgs.iter().map(|_| CollectedShares::new()).collect();
+
+    // Node IDs are not share indices: the share index is the position in the committee.
+    for (idx, (id, url)) in session.members.iter().enumerate() {
+        let pending: Vec<usize> = (0..msgs.len())
+            .filter(|&i| all_shares[i].by_idx.len() <= pub_key_set.threshold())
+            .collect();
+        if pending.is_empty() {
+            break;
+        }
+        if session.absent.contains(id) {
+            continue;
+        }
+        let share_req = SignBatchReq {
+            session_id: req_body.session_id,
+            context,
+            msgs: pending.iter().map(|&i| msgs[i].clone()).collect(),
+        };
+        let resp: SignShareBatchResp = match post_req(url, "sign_share_batch", &share_req).await {
+            Ok(resp) => resp,
+            Err(e) => {
+                println!("Node #{} sent no signature shares: {}", id, e);
+                continue;
+            }
+        };
+        if resp.node_idx != idx || resp.sig_shares.len() != pending.len() {
+            println!("Node #{} sent an invalid batch of signature shares", id);
+            for &i in &pending {
+                all_shares[i].rejected.push(*id);
+            }
+            continue;
+        }
+        // Checking the shares takes pairings: it runs off the async runtime.
+        let pub_key_share = pub_key_set.public_key_share(idx);
+        let pending_msgs = share_req.msgs;
+        let checked = tokio::task::spawn_blocking(move || {
+            let sig_shares = resp.sig_shares.into_iter().zip(&pending_msgs);
+            sig_shares
+                .map(|(sig_share, msg)| {
+                    let is_valid = context.verify_share(&pub_key_share, &sig_share, msg);
+                    (sig_share, is_valid)
+                })
+                .collect::<Vec<_>>()
+        })
         .await
-        .unwrap();
-    println!("is_success: {:?}", is_success);
-    Json(())
+        .map_err(internal_error)?;
+        for (&i, (sig_share, is_valid)) in pending.iter().zip(checked) {
+            let shares = &mut all_shares[i];
+            if is_valid {
+                shares.by_idx.insert(idx, sig_share);
+                shares.contributors.push(*id);
+            } else {
+                println!("Node #{} sent an invalid signature share #{}", id, i);
+                shares.rejected.push(*id);
+            }
+        }
+    }
+
+    let results = msgs
+        .iter()
+        .zip(all_shares)
+        .map(|(msg, shares)| {
+            if shares.by_idx.len() <= pub_key_set.threshold() {
+                return Err(format!(
+                    "Only {} valid signature shares, {} are needed",
+                    shares.by_idx.len(),
+                    pub_key_set.threshold() + 1
+                ));
+            }
+            let signature = pub_key_set
+                .combine_signatures(&shares.by_idx)
+                .map_err(|e| format!("Failed to combine signature shares: {:?}", e))?;
+            if !context.verify(&pub_key_set.public_key(), &signature, msg) {
+                return Err("The combined signature is invalid".to_string());
+            }
+            Ok(SignResp {
+                signature,
+                contributors: shares.contributors,
+                rejected: shares.rejected,
+            })
+        })
+        .collect();
+    Ok(Json(SignBatchResp { results }))
 }
 
-async fn init_dkg_req(domain: &str, body: &InitDkgReq) -> Result<InitDkgResp, Box<dyn Error>> {
-    let url = format!("{}/init_dkg", domain);
-    let client: Client = Client::new();
-    let response = client.post(&url).json(body).send().await.unwrap();
-    let response_text = response.text().await.unwrap();
-    let resp: InitDkgResp = serde_json::from_str(&response_text).unwrap();
-    Ok(resp)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconShareReq {
+    session_id: usize,
+    round: u64,
+    prev: Option<BeaconRound>,
 }
 
-async fn commit_req(domain: &str, body: &CommitReq) -> Result<CommitResp, Box<dyn Error>> {
-    let url = format!("{}/commit", domain);
-    let client = Client::new();
-    let response = client.post(&url).json(body).send().await?;
-    let response_text = response.text().await?;
-    let resp: CommitResp = serde_json::from_str(&response_text)?;
-    Ok(resp)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconShareResp {
+    node_idx: usize,
+    sig_share: SignatureShare,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconReq {
+    session_id: usize,
+    /// The first round to return.
+    #[serde(default)]
+    from_round: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconResp {
+    /// The master public key the rounds are verified with.
+    public_key: threshold_crypto::PublicKey,
+    rounds: Vec<BeaconRound>,
 }
 
-async fn finalize_dkg_req(
+/// Returns the session's beacon rounds, starting at `from_round`. They can be checked with
+/// `dkg::verify_beacon_chain` and only the master public key.
+async fn beacon(
+    State(state): State<AppState>,
+    Json(req_body): Json<BeaconReq>,
+) -> Result<Json<BeaconResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let from_round = (req_body.from_round as usize).min(session.beacon.len());
+    Ok(Json(BeaconResp {
+        public_key: pub_key_set.public_key(),
+        rounds: session.beacon[from_round..].to_vec(),
+    }))
+}
+
+/// Collects signature shares of the next beacon round from the members until `threshold + 1` of
+/// them are valid, combines them, and appends the round to the session's chain. The round must be
+/// due: the members refuse to sign it earlier.
+async fn next_beacon_round(
+    state: &AppState,
+    schedule: &BeaconSchedule,
+    session_id: usize,
+) -> Result<BeaconRound, HandlerError> {
+    let session = get_session(&state.db, session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .as_ref()
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", session_id)))?;
+    let prev = session.beacon.last().cloned();
+    let round = prev.as_ref().map_or(0, |prev| prev.round + 1);
+    if !schedule.is_due(round, unix_time()) {
+        return Err(bad_request(format!(
+            "Beacon round {} is not due yet",
+            round
+        )));
+    }
+    let prev_signature = prev.as_ref().map(|prev| prev.signature.clone());
+    let msg = BeaconRound::message(round, prev_signature.as_ref());
+    let share_req = BeaconShareReq {
+        session_id,
+        round,
+        prev,
+    };
+
+    let shares = collect_shares::<_, BeaconShareResp, _>(
+        &session,
+        pub_key_set,
+        "beacon_share",
+        &share_req,
+        pub_key_set.threshold() + 1,
+        |idx, sig_share| {
+            let pk_share = pub_key_set.public_key_share(idx);
+            SigningContext::Beacon.verify_share(&pk_share, sig_share, &msg)
+        },
+    )
+    .await?;
+    let signature = pub_key_set
+        .combine_signatures(&shares.by_idx)
+        .map_err(|e| bad_gateway(format!("Failed to combine signature shares: {:?}", e)))?;
+    let beacon_round = BeaconRound::new(round, prev_signature, signature);
+    if !beacon_round.verify(&pub_key_set.public_key()) {
+        return Err(bad_gateway("The combined beacon round is invalid"));
+    }
+
+    let mut db = state.db.write().unwrap();
+    let session = db
+        .get_mut(&session_id)
+        .ok_or_else(|| bad_request(format!("Unknown session #{}", session_id)))?;
+    // Another round may have been appended in the meantime.
+    if session.beacon.len() as u64 != round {
+        return Err(bad_gateway(format!(
+            "Beacon round {} already exists",
+            round
+        )));
+    }
+    session.beacon.push(beacon_round.clone());
+    Ok(beacon_round)
+}
+
+/// Returns the context, unless it is reserved for the messages of its protocol. The members
+/// refuse to sign in a reserved context on request.
+fn open_context(context: SigningContext) -> Result<SigningContext, HandlerError> {
+    if !context.is_open() {
+        return Err(bad_request(format!(
+            "The {} context is reserved",
+            context.tag()
+        )));
+    }
+    Ok(context)
+}
+
+/// Refreshes the key shares of every finalized session once per `interval`.
+async fn refresh_periodically(state: AppState, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.tick().await; // The first tick completes immediately.
+    loop {
+        ticks.tick().await;
+        let session_ids: Vec<usize> = state
+            .db
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| session.pub_key_set.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        for session_id in session_ids {
+            match refresh_shares(&state, session_id).await {
+                Ok(resp) => println!(
+                    "Refreshed the key shares of session #{}, is_success: {:?}",
+                    session_id, resp.is_success
+                ),
+                Err((_, e)) => println!(
+                    "Failed to refresh the key shares of session #{}: {}",
+                    session_id, e
+                ),
+            }
+        }
+    }
+}
+
+/// Runs the rounds of the randomness beacon of every finalized session as they become due. A
+/// chain that is behind, e.g. because its session was finalized after the genesis, catches up
+/// round by round.
+async fn beacon_periodically(state: AppState, schedule: BeaconSchedule) {
+    let mut ticks = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        ticks.tick().await;
+        let session_ids: Vec<usize> = state
+            .db
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, session)| session.pub_key_set.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        for session_id in session_ids {
+            loop {
+                let round = match state.db.read().unwrap().get(&session_id) {
+                    Some(session) => session.beacon.len() as u64,
+                    None => break,
+                };
+                if !schedule.is_due(round, unix_time()) {
+                    break;
+                }
+                match next_beacon_round(&state, &schedule, session_id).await {
+                    Ok(round) => println!(
+                        "Beacon round {} of session #{}: {:?}",
+                        round.round, session_id, round.randomness
+                    ),
+                    // Retried on the next tick, e.g. if a member's clock is behind ours.
+                    Err((_, e)) => {
+                        println!(
+                            "Failed to run beacon round {} of session #{}: {}",
+                            round, session_id, e
+                        );
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn get_session(db: &Db, session_id: usize) -> Result<Session, HandlerError> {
+    db.read()
+        .unwrap()
+        .get(&session_id)
+        .cloned()
+        .ok_or_else(|| bad_request(format!("Unknown session #{}", session_id)))
+}
+
+fn bad_request<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+fn conflict<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::CONFLICT, err.to_string())
+}
+
+fn bad_gateway<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::BAD_GATEWAY, err.to_string())
+}
+
+fn internal_error<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is before the Unix epoch.")
+        .as_secs()
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Sends the request to all nodes at once and returns the responses that arrive before the
+/// deadline, by node ID, together with the nodes that missed it. A node that can't be reached or
+/// returns an error counts as absent, like one that missed the deadline.
+async fn broadcast<B, R>(
+    nodes: &BTreeMap<usize, String>,
+    route: &str,
+    body: &B,
+    deadline: Duration,
+) -> Result<(BTreeMap<usize, R>, BTreeSet<usize>), HandlerError>
+where
+    B: Serialize,
+    R: DeserializeOwned + Send + 'static,
+{
+    let body = Arc::new(serde_json::to_value(body).map_err(bad_request)?);
+    let deadline = Instant::now() + deadline;
+    let mut requests = JoinSet::new();
+    for (id, url) in nodes.iter() {
+        let (id, url, route, body) = (*id, url.clone(), route.to_string(), body.clone());
+        requests.spawn(async move {
+            let resp = post_req::<_, R>(&url, &route, &*body);
+            (id, tokio::time::timeout_at(deadline, resp).await)
+        });
+    }
+
+    let mut resps = BTreeMap::new();
+    let mut absent = BTreeSet::new();
+    while let Some(joined) = requests.join_next().await {
+        match joined.map_err(bad_gateway)? {
+            (id, Ok(Ok(resp))) => {
+                resps.insert(id, resp);
+            }
+            (id, Ok(Err(e))) => {
+                println!("Node #{} failed to answer {}: {}", id, route, e);
+                absent.insert(id);
+            }
+            (id, Err(_)) => {
+                println!("Node #{} missed the {} deadline", id, route);
+                absent.insert(id);
+            }
+        }
+    }
+    Ok((resps, absent))
+}
+
+async fn post_req<B, R>(
     domain: &str,
-    body: &FinalizeReq,
-) -> Result<FinalizeResp, Box<dyn Error>> {
-    let url = format!("{}/finalize_dkg", domain);
+    route: &str,
+    body: &B,
+) -> Result<R, Box<dyn Error + Send + Sync>>
+where
+    B: Serialize,
+    R: DeserializeOwned,
+{
+    let url = format!("{}/{}", domain, route);
     let client = Client::new();
     let response = client.post(&url).json(body).send().await?;
+    let status = response.status();
     let response_text = response.text().await?;
-    let resp: FinalizeResp = serde_json::from_str(&response_text)?;
+    if !status.is_success() {
+        return Err(format!("{} returned {}: {}", url, status, response_text).into());
+    }
+    let resp: R = serde_json::from_str(&response_text)?;
     Ok(resp)
 }
@@ -0,0 +1,329 @@
+//! Streaming encryption of large payloads to a threshold key.
+//!
+//! A random data key is encrypted to the master public key in the stream header, and the payload
+//! is split into chunks that are encrypted with ChaCha20-Poly1305 under the data key, like in the
+//! STREAM construction: each chunk's nonce contains its index and a flag that marks the last
+//! chunk, so chunks can't be reordered, dropped or appended, and the stream can't be truncated.
+//! Decrypting needs a single threshold decryption of the data key, and only one chunk is held in
+//! memory at a time.
+
+use std::io::{self, Read, Write};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use threshold_crypto::{Ciphertext, PublicKey};
+
+/// The default number of plaintext bytes per chunk.
+pub const DEFAULT_CHUNK_SIZE: u32 = 64 * 1024;
+/// The largest chunk size we decrypt, so that a forged header can't make us allocate too much.
+pub const MAX_CHUNK_SIZE: u32 = 16 * 1024 * 1024;
+/// The length of a data key, in bytes.
+const KEY_LEN: usize = 32;
+/// The length of the random nonce prefix. The rest of the nonce is the chunk index and the flag.
+const NONCE_PREFIX_LEN: usize = 7;
+/// The length of a Poly1305 tag, added to every chunk.
+const TAG_LEN: usize = 16;
+/// The largest header we read.
+const MAX_HEADER_LEN: u32 = 64 * 1024;
+
+/// An error while encrypting or decrypting a stream.
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Reading the input or writing the output failed.
+    #[fail(display = "I/O error: {}", _0)]
+    Io(#[cause] io::Error),
+    /// The header can't be read.
+    #[fail(display = "Invalid stream header: {}", _0)]
+    InvalidHeader(String),
+    /// The threshold-encrypted data key is not well formed.
+    #[fail(display = "Invalid data key ciphertext")]
+    InvalidKeyCiphertext,
+    /// The chunk size is zero or too large.
+    #[fail(display = "Invalid chunk size {}", _0)]
+    ChunkSize(u32),
+    /// The data key has the wrong length.
+    #[fail(display = "Invalid data key")]
+    InvalidDataKey,
+    /// A chunk has been tampered with or moved, or the data key is wrong.
+    #[fail(display = "Chunk {} failed authentication", _0)]
+    Authentication(u32),
+    /// The stream ended before its last chunk.
+    #[fail(display = "The stream is truncated")]
+    Truncated,
+    /// There is data after the last chunk.
+    #[fail(display = "Unexpected data after the last chunk")]
+    TrailingData,
+    /// The stream has more chunks than the nonce can count.
+    #[fail(display = "The stream is too long")]
+    TooLong,
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Error {
+        Error::InvalidHeader(format!("{:?}", err))
+    }
+}
+
+/// The header at the start of an encrypted stream. It is authenticated together with every chunk.
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct StreamHeader {
+    key_ct: Ciphertext,
+    nonce_prefix: [u8; NONCE_PREFIX_LEN],
+    chunk_size: u32,
+}
+
+impl StreamHeader {
+    /// Returns the data key ciphertext. Its threshold decryption is the data key to decrypt the
+    /// stream with.
+    pub fn key_ciphertext(&self) -> &Ciphertext {
+        &self.key_ct
+    }
+
+    /// Reads the header from the start of an encrypted stream.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<StreamHeader, Error> {
+        let mut len_bytes = [0; 4];
+        reader.read_exact(&mut len_bytes)?;
+        let len = u32::from_be_bytes(len_bytes);
+        if len > MAX_HEADER_LEN {
+            return Err(Error::InvalidHeader(format!("{} bytes long", len)));
+        }
+        let mut ser_header = vec![0; len as usize];
+        reader.read_exact(&mut ser_header)?;
+        let header: StreamHeader = bincode::deserialize(&ser_header)?;
+        if header.chunk_size == 0 || header.chunk_size > MAX_CHUNK_SIZE {
+            return Err(Error::ChunkSize(header.chunk_size));
+        }
+        if !header.key_ct.verify() {
+            return Err(Error::InvalidKeyCiphertext);
+        }
+        Ok(header)
+    }
+
+    /// Writes the length-prefixed header.
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let ser_header = bincode::serialize(self)?;
+        writer.write_all(&(ser_header.len() as u32).to_be_bytes())?;
+        writer.write_all(&ser_header)?;
+        Ok(())
+    }
+
+    /// Returns the nonce of the chunk with the given index.
+    fn nonce(&self, idx: u32, is_last: bool) -> [u8; 12] {
+        let mut nonce = [0; 12];
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&self.nonce_prefix);
+        nonce[NONCE_PREFIX_LEN..11].copy_from_slice(&idx.to_be_bytes());
+        nonce[11] = is_last as u8;
+        nonce
+    }
+}
+
+/// Encrypts everything `reader` returns to the public key, in chunks of `chunk_size` bytes, and
+/// writes the header and the encrypted chunks to `writer`. Returns the number of plaintext bytes.
+pub fn encrypt<R, W, G>(
+    pk: &PublicKey,
+    chunk_size: u32,
+    reader: &mut R,
+    writer: &mut W,
+    rng: &mut G,
+) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+    G: Rng,
+{
+    if chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+        return Err(Error::ChunkSize(chunk_size));
+    }
+    let data_key: [u8; KEY_LEN] = rng.gen();
+    let header = StreamHeader {
+        key_ct: pk.encrypt_with_rng(rng, data_key),
+        nonce_prefix: rng.gen(),
+        chunk_size,
+    };
+    header.write_to(writer)?;
+    let aad = bincode::serialize(&header)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&data_key));
+
+    // A chunk is the last one if it is shorter than `chunk_size`, possibly empty.
+    let mut chunk = vec![0; chunk_size as usize];
+    let mut total = 0;
+    for idx in 0.. {
+        let len = read_full(reader, &mut chunk)?;
+        let is_last = len < chunk.len();
+        let payload = Payload {
+            msg: &chunk[..len],
+            aad: &aad,
+        };
+        let nonce = header.nonce(idx, is_last);
+        let ct = cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Authentication(idx))?;
+        writer.write_all(&ct)?;
+        total += len as u64;
+        if is_last {
+            writer.flush()?;
+            return Ok(total);
+        }
+        if idx == u32::MAX {
+            break;
+        }
+    }
+    Err(Error::TooLong)
+}
+
+/// Decrypts the chunks following the header with the data key, and writes the plaintext to
+/// `writer` as it is authenticated. Returns the number of plaintext bytes.
+///
+/// If this fails, e.g. because the stream is truncated, the plaintext written so far must be
+/// discarded.
+pub fn decrypt<R, W>(
+    header: &StreamHeader,
+    data_key: &[u8],
+    reader: &mut R,
+    writer: &mut W,
+) -> Result<u64, Error>
+where
+    R: Read,
+    W: Write,
+{
+    if data_key.len() != KEY_LEN {
+        return Err(Error::InvalidDataKey);
+    }
+    let aad = bincode::serialize(header)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(data_key));
+
+    // Only the last chunk is shorter than a full encrypted chunk.
+    let mut chunk = vec![0; header.chunk_size as usize + TAG_LEN];
+    let mut total = 0;
+    for idx in 0.. {
+        let len = read_full(reader, &mut chunk)?;
+        let is_last = len < chunk.len();
+        if is_last && len < TAG_LEN {
+            return Err(Error::Truncated);
+        }
+        let payload = Payload {
+            msg: &chunk[..len],
+            aad: &aad,
+        };
+        let nonce = header.nonce(idx, is_last);
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Authentication(idx))?;
+        writer.write_all(&plaintext)?;
+        total += plaintext.len() as u64;
+        if is_last {
+            if read_full(reader, &mut [0])? != 0 {
+                return Err(Error::TrailingData);
+            }
+            writer.flush()?;
+            return Ok(total);
+        }
+        if idx == u32::MAX {
+            break;
+        }
+    }
+    Err(Error::TooLong)
+}
+
+/// Reads until the buffer is full or the reader is exhausted, and returns the number of bytes
+/// read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+// test
+#[cfg(test)]
+mod test {
+    use super::{decrypt, encrypt, Error, StreamHeader, TAG_LEN};
+    use threshold_crypto::SecretKeySet;
+
+    #[test]
+    fn test_stream() {
+        let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let pk_set = sk_set.public_keys();
+        let chunk_size = 10;
+        let msg: Vec<u8> = (0..35).collect();
+
+        let mut ct = Vec::new();
+        let len = encrypt(
+            &pk_set.public_key(),
+            chunk_size,
+            &mut &msg[..],
+            &mut ct,
+            &mut rng,
+        )
+        .expect("Failed to encrypt");
+        assert_eq!(len, msg.len() as u64);
+
+        // A single threshold decryption of the data key decrypts the whole stream.
+        let decrypt_all = |ct: &[u8]| -> Result<Vec<u8>, Error> {
+            let mut reader = ct;
+            let header = StreamHeader::read_from(&mut reader)?;
+            let dec_shares: Vec<_> = [0, 1]
+                .iter()
+                .map(|&i| {
+                    let sks = sk_set.secret_key_share(i);
+                    (i, sks.decrypt_share(header.key_ciphertext()).unwrap())
+                })
+                .collect();
+            let shares = dec_shares.iter().map(|(i, share)| (*i, share));
+            let data_key = pk_set.decrypt(shares, header.key_ciphertext()).unwrap();
+            let mut plaintext = Vec::new();
+            decrypt(&header, &data_key, &mut reader, &mut plaintext)?;
+            Ok(plaintext)
+        };
+        assert_eq!(decrypt_all(&ct).unwrap(), msg);
+
+        // The stream ends with the last chunk of 5 bytes, after three full chunks.
+        let full_chunk = chunk_size as usize + TAG_LEN;
+        let chunks_start = ct.len() - 3 * full_chunk - (5 + TAG_LEN);
+
+        // Truncation at a chunk boundary is detected.
+        match decrypt_all(&ct[..ct.len() - 5 - TAG_LEN]) {
+            Err(Error::Truncated) => (),
+            result => panic!("Truncation not detected: {:?}", result),
+        }
+        // Swapping two chunks is detected.
+        let mut swapped = ct[..chunks_start].to_vec();
+        swapped.extend_from_slice(&ct[chunks_start + full_chunk..chunks_start + 2 * full_chunk]);
+        swapped.extend_from_slice(&ct[chunks_start..chunks_start + full_chunk]);
+        swapped.extend_from_slice(&ct[chunks_start + 2 * full_chunk..]);
+        match decrypt_all(&swapped) {
+            Err(Error::Authentication(0)) => (),
+            result => panic!("Reordering not detected: {:?}", result),
+        }
+        // Appending data is detected.
+        let mut appended = ct.clone();
+        appended.push(0);
+        match decrypt_all(&appended) {
+            Err(Error::TrailingData) => (),
+            result => panic!("Trailing data not detected: {:?}", result),
+        }
+
+        // A multiple of the chunk size ends with an empty chunk.
+        let mut ct = Vec::new();
+        encrypt(&pk_set.public_key(), 7, &mut &msg[..], &mut ct, &mut rng).unwrap();
+        assert_eq!(decrypt_all(&ct).unwrap(), msg);
+    }
+}
@@ -1,6 +1,6 @@
 pub mod dkg;
 pub mod errors;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use dkg::{Ack, AckOutcome, Part, PartOutcome, PubKeyMap, SyncKeyGen};
 use errors::{error_to_c_string, ErrorFFIKind};
 use serde::{Deserialize, Serialize};
@@ -290,6 +290,14 @@ fn finalize_dkg(req_body: FinalizeReq) -> Result<FinalizeResp> {
     });
     assert_eq!(pks, pub_key_set); // All nodes now know the public keys and public key shares.
 
+    // A corrupt share would combine into a wrong signature, without a hint of the cause.
+    let is_valid_share_1 = pub_key_set
+        .public_key_share(1)
+        .verify(&req_body.sig_share_1, &req_body.signed_msg_1);
+    if !is_valid_share_1 {
+        bail!("Node #1 sent an invalid signature share");
+    }
+
     let sks_0 = opt_sks.expect("Not an observer node: We receive a secret key share.");
     let sig_share_0 = sks_0.sign(req_body.signed_msg_1.clone());
     let mut sig_shares: BTreeMap<usize, SignatureShare> = BTreeMap::new();
@@ -297,7 +305,7 @@ fn finalize_dkg(req_body: FinalizeReq) -> Result<FinalizeResp> {
     sig_shares.insert(1, req_body.sig_share_1);
     let combine_sig = pub_key_set
         .combine_signatures(&sig_shares)
-        .expect("The shares can be combined.");
+        .map_err(|e| anyhow!("Failed to combine signature shares: {:?}", e))?;
 
     let is_success = pub_key_set
         .public_key()
@@ -0,0 +1,280 @@
+//! A versioned envelope around a threshold ciphertext, that identifies the key it was encrypted
+//! to, so that the nodes and the combiner can pick the right key share.
+
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use threshold_crypto::{Ciphertext, DecryptionShare, IntoFr, PublicKeySet};
+
+use crate::hybrid::{self, HybridCiphertext};
+
+/// The current envelope format version.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// The encryption scheme of the ciphertext in an `Envelope`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Scheme {
+    /// A `Ciphertext` of the message. It can't authenticate associated data.
+    Threshold,
+    /// A `HybridCiphertext` of the message, authenticating the associated data and the header.
+    HybridChaCha20Poly1305,
+}
+
+impl Default for Scheme {
+    fn default() -> Scheme {
+        Scheme::HybridChaCha20Poly1305
+    }
+}
+
+impl Scheme {
+    /// Returns the scheme ID in the envelope.
+    fn id(self) -> u32 {
+        match self {
+            Scheme::Threshold => 1,
+            Scheme::HybridChaCha20Poly1305 => 2,
+        }
+    }
+
+    /// Returns the scheme with the given ID, or `None` if we don't support it.
+    fn from_id(id: u32) -> Option<Scheme> {
+        match id {
+            1 => Some(Scheme::Threshold),
+            2 => Some(Scheme::HybridChaCha20Poly1305),
+            _ => None,
+        }
+    }
+}
+
+/// An error while sealing or opening an `Envelope`.
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum Error {
+    /// The envelope has a format version we don't understand.
+    #[fail(display = "Unsupported envelope version {}", _0)]
+    UnsupportedVersion(u32),
+    /// The envelope has a scheme ID we don't understand.
+    #[fail(display = "Unsupported encryption scheme {}", _0)]
+    UnsupportedScheme(u32),
+    /// The scheme can't authenticate associated data.
+    #[fail(display = "The scheme doesn't support associated data")]
+    AssociatedData,
+    /// The envelope was encrypted to a different key.
+    #[fail(display = "The envelope was encrypted to a different key")]
+    KeyMismatch,
+    /// The ciphertext is not well formed.
+    #[fail(display = "Invalid ciphertext")]
+    InvalidCiphertext,
+    /// The decryption shares can't be combined.
+    #[fail(display = "Error combining decryption shares: {}", _0)]
+    Combine(String),
+    /// The hybrid ciphertext can't be decrypted.
+    #[fail(display = "{}", _0)]
+    Hybrid(hybrid::Error),
+    /// Failed to serialize or deserialize the ciphertext.
+    #[fail(display = "Serialization error: {}", _0)]
+    Serialize(String),
+}
+
+impl From<bincode::Error> for Error {
+    fn from(err: bincode::Error) -> Error {
+        Error::Serialize(format!("{:?}", err))
+    }
+}
+
+impl From<hybrid::Error> for Error {
+    fn from(err: hybrid::Error) -> Error {
+        Error::Hybrid(err)
+    }
+}
+
+/// Returns the fingerprint of a key set: the SHA-256 hash of its master public key. It stays the
+/// same when the key shares are refreshed or reshared.
+pub fn fingerprint(pub_key_set: &PublicKeySet) -> Result<[u8; 32], Error> {
+    let ser_pk = bincode::serialize(&pub_key_set.public_key())?;
+    Ok(Sha256::digest(&ser_pk).into())
+}
+
+/// A ciphertext together with its format version, scheme, the fingerprint of the key it was
+/// encrypted to, and the associated data, if any.
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct Envelope {
+    version: u32,
+    scheme: u32,
+    key_fingerprint: [u8; 32],
+    associated_data: Vec<u8>,
+    /// The serialized ciphertext of the scheme.
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Encrypts the message to the master public key of the key set with the given scheme. The
+    /// associated data must be empty unless the scheme authenticates it.
+    pub fn seal<M: AsRef<[u8]>, R: Rng>(
+        scheme: Scheme,
+        pub_key_set: &PublicKeySet,
+        msg: M,
+        associated_data: &[u8],
+        rng: &mut R,
+    ) -> Result<Envelope, Error> {
+        let mut envelope = Envelope {
+            version: ENVELOPE_VERSION,
+            scheme: scheme.id(),
+            key_fingerprint: fingerprint(pub_key_set)?,
+            associated_data: associated_data.to_vec(),
+            ciphertext: Vec::new(),
+        };
+        let pk = pub_key_set.public_key();
+        envelope.ciphertext = match scheme {
+            Scheme::Threshold if !associated_data.is_empty() => return Err(Error::AssociatedData),
+            Scheme::Threshold => bincode::serialize(&pk.encrypt_with_rng(rng, msg))?,
+            Scheme::HybridChaCha20Poly1305 => {
+                let ad = envelope.authenticated_header()?;
+                bincode::serialize(&HybridCiphertext::encrypt(&pk, msg, &ad, rng)?)?
+            }
+        };
+        Ok(envelope)
+    }
+
+    /// Returns the fingerprint of the key the envelope was encrypted to.
+    pub fn key_fingerprint(&self) -> &[u8; 32] {
+        &self.key_fingerprint
+    }
+
+    /// Returns the associated data.
+    pub fn associated_data(&self) -> &[u8] {
+        &self.associated_data
+    }
+
+    /// Returns the scheme, or fails if we don't understand the version or the scheme.
+    pub fn scheme(&self) -> Result<Scheme, Error> {
+        if self.version != ENVELOPE_VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        Scheme::from_id(self.scheme).ok_or(Error::UnsupportedScheme(self.scheme))
+    }
+
+    /// Returns the well-formed threshold ciphertext the decryption shares are computed for.
+    pub fn key_ciphertext(&self) -> Result<Ciphertext, Error> {
+        let ct = match self.scheme()? {
+            Scheme::Threshold => bincode::deserialize(&self.ciphertext)?,
+            Scheme::HybridChaCha20Poly1305 => {
+                let ct: HybridCiphertext = bincode::deserialize(&self.ciphertext)?;
+                ct.key_ciphertext().clone()
+            }
+        };
+        if !ct.verify() {
+            return Err(Error::InvalidCiphertext);
+        }
+        Ok(ct)
+    }
+
+    /// Combines more than `threshold` decryption shares of the `key_ciphertext`, by share index,
+    /// and returns the message. The shares must have been verified. Fails with `AssociatedData`
+    /// if the scheme can't authenticate the envelope's associated data.
+    pub fn open<'a, T, I>(&self, pub_key_set: &PublicKeySet, shares: I) -> Result<Vec<u8>, Error>
+    where
+        I: IntoIterator<Item = (T, &'a DecryptionShare)>,
+        T: IntoFr,
+    {
+        if self.key_fingerprint != fingerprint(pub_key_set)? {
+            return Err(Error::KeyMismatch);
+        }
+        match self.scheme()? {
+            Scheme::Threshold if !self.associated_data.is_empty() => Err(Error::AssociatedData),
+            Scheme::Threshold => {
+                let ct = self.key_ciphertext()?;
+                let msg = pub_key_set.decrypt(shares, &ct);
+                msg.map_err(|err| Error::Combine(err.to_string()))
+            }
+            Scheme::HybridChaCha20Poly1305 => {
+                let ct: HybridCiphertext = bincode::deserialize(&self.ciphertext)?;
+                let ad = self.authenticated_header()?;
+                Ok(ct.decrypt(pub_key_set, shares, &ad)?)
+            }
+        }
+    }
+
+    /// Returns the serialized header, i.e. everything but the ciphertext, that the hybrid scheme
+    /// authenticates.
+    fn authenticated_header(&self) -> Result<Vec<u8>, Error> {
+        let header = (
+            self.version,
+            self.scheme,
+            &self.key_fingerprint,
+            &self.associated_data,
+        );
+        Ok(bincode::serialize(&header)?)
+    }
+}
+
+// test
+#[cfg(test)]
+mod test {
+    use super::{fingerprint, Envelope, Error, Scheme};
+    use std::collections::BTreeMap;
+    use threshold_crypto::{Ciphertext, SecretKeySet};
+
+    #[test]
+    fn test_envelope() {
+        let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+        let sk_set = SecretKeySet::random(1, &mut rng);
+        let pk_set = sk_set.public_keys();
+        let other_pk_set = SecretKeySet::random(1, &mut rng).public_keys();
+        let other_fingerprint = fingerprint(&other_pk_set).unwrap();
+        let msg = b"Enveloped";
+        let decrypt_share = |i: usize, ct: &Ciphertext| {
+            let dec_share = sk_set.secret_key_share(i).decrypt_share(ct);
+            dec_share.expect("Invalid ciphertext")
+        };
+
+        for &(scheme, ad) in &[
+            (Scheme::Threshold, &b""[..]),
+            (Scheme::HybridChaCha20Poly1305, &b"Record #42"[..]),
+        ] {
+            let envelope = Envelope::seal(scheme, &pk_set, msg, ad, &mut rng).unwrap();
+            assert_eq!(envelope.scheme(), Ok(scheme));
+            assert_eq!(envelope.key_fingerprint(), &fingerprint(&pk_set).unwrap());
+            assert_ne!(envelope.key_fingerprint(), &other_fingerprint);
+            assert_eq!(envelope.associated_data(), ad);
+
+            let key_ct = envelope.key_ciphertext().unwrap();
+            let dec_shares: BTreeMap<usize, _> = [0, 2]
+                .iter()
+                .map(|&i| (i, decrypt_share(i, &key_ct)))
+                .collect();
+            assert_eq!(envelope.open(&pk_set, &dec_shares), Ok(msg.to_vec()));
+            let result = envelope.open(&other_pk_set, &dec_shares);
+            assert_eq!(result, Err(Error::KeyMismatch));
+
+            // Unknown versions and schemes are rejected.
+            let mut future = envelope.clone();
+            future.version = 2;
+            assert_eq!(future.key_ciphertext(), Err(Error::UnsupportedVersion(2)));
+            let mut unknown = envelope.clone();
+            unknown.scheme = 7;
+            assert_eq!(unknown.key_ciphertext(), Err(Error::UnsupportedScheme(7)));
+        }
+
+        // The hybrid scheme authenticates the associated data, the threshold scheme can't.
+        let scheme = Scheme::HybridChaCha20Poly1305;
+        let mut envelope = Envelope::seal(scheme, &pk_set, msg, b"Record #42", &mut rng).unwrap();
+        envelope.associated_data = b"Record #43".to_vec();
+        let key_ct = envelope.key_ciphertext().unwrap();
+        let dec_shares: BTreeMap<usize, _> = [0, 1]
+            .iter()
+            .map(|&i| (i, decrypt_share(i, &key_ct)))
+            .collect();
+        assert!(envelope.open(&pk_set, &dec_shares).is_err());
+        let result = Envelope::seal(Scheme::Threshold, &pk_set, msg, b"Record #42", &mut rng);
+        assert_eq!(result, Err(Error::AssociatedData));
+        let mut envelope = Envelope::seal(Scheme::Threshold, &pk_set, msg, b"", &mut rng).unwrap();
+        envelope.associated_data = b"Record #42".to_vec();
+        let key_ct = envelope.key_ciphertext().unwrap();
+        let dec_shares: BTreeMap<usize, _> = [0, 1]
+            .iter()
+            .map(|&i| (i, decrypt_share(i, &key_ct)))
+            .collect();
+        let result = envelope.open(&pk_set, &dec_shares);
+        assert_eq!(result, Err(Error::AssociatedData));
+    }
+}
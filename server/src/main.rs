@@ -1,46 +1,264 @@
+pub mod base64_bytes;
 pub mod dkg;
+pub mod envelope;
+pub mod hybrid;
 pub mod sqlite;
+pub mod store;
 use axum::{
     error_handling::HandleErrorLayer, extract::State, http::StatusCode, response::IntoResponse,
     routing::post, Json, Router,
 };
 use axum_macros::debug_handler;
-use dkg::{Ack, AckOutcome, Part, PartOutcome, PubKeyMap, SyncKeyGen};
+use dkg::{
+    Ack, AckFault, AckOutcome, AsyncKeyGen, BeaconRound, BeaconSchedule, Complaint,
+    ComplaintOutcome, Fault, FaultKind, Justification, KeyGenSnapshot, KeyShare,
+    LabelledCiphertext, LabelledDecryptionShare, Part, PartOutcome, Phase, PubKeyMap, QualifiedSet,
+    RefreshPart, SigningContext, SyncKeyGen, ThresholdParams, Transcript,
+    DEFAULT_BEACON_PERIOD_SECS, DEFAULT_MIN_QUORUM,
+};
+use envelope::{Envelope, Scheme};
+use hybrid::HybridCiphertext;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
+    borrow::Borrow,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     net::SocketAddr,
     sync::{Arc, RwLock},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use store::Store;
+use threshold_crypto::{
+    Ciphertext, DecryptionShare, PublicKeySet, SecretKey, Signature, SignatureShare,
 };
-use threshold_crypto::{SecretKey, SignatureShare};
 use tokio::sync::Mutex;
 use tower::{BoxError, ServiceBuilder};
 use tower_http::trace::TraceLayer;
 
 #[derive(Debug, Clone)]
 struct Session {
-    sk: SecretKey,
-    node: Arc<Mutex<SyncKeyGen<usize>>>,
-    parts: Vec<Part>,
-    acks: Vec<Ack>,
+    node: Arc<Mutex<AsyncKeyGen<usize>>>,
+    params: ThresholdParams,
+    parts: BTreeMap<usize, Part>,
+    acks: Vec<(usize, Ack)>,
+    pub_key_set: Option<PublicKeySet>,
+    key_share: Option<KeyShare>,
+    /// The faults we detected in the current key generation.
+    faults: Vec<Fault<usize>>,
+    /// The latest beacon round we signed a share of.
+    beacon_round: Option<u64>,
+}
+
+/// The current format version of a `SessionSnapshot`. Versions 1 and 2 are taken by the
+/// unversioned format, which started with the `KeyGenSnapshot` and its own version.
+const SESSION_SNAPSHOT_VERSION: u32 = 4;
+
+/// The most ciphertexts we decrypt in one batch, so that a batch finishes within the request
+/// timeout.
+const MAX_BATCH_SIZE: usize = 256;
+
+/// The persisted form of a `Session`, starting with its format version. Our secret values are
+/// encrypted to our own key.
+#[derive(Deserialize, Serialize)]
+struct SessionSnapshot {
+    /// The format version, `SESSION_SNAPSHOT_VERSION` when the snapshot was taken.
+    version: u32,
+    /// The serialized `KeyGenSnapshot`, which has a format version of its own.
+    node: Vec<u8>,
+    params: ThresholdParams,
+    parts: BTreeMap<usize, Part>,
+    acks: Vec<(usize, Ack)>,
+    pub_key_set: Option<PublicKeySet>,
+    /// Our encrypted key share.
+    key_share: Option<Vec<u8>>,
+    faults: Vec<Fault<usize>>,
+    beacon_round: Option<u64>,
+}
+
+/// A `SessionSnapshot` in format version 3, before we kept track of the beacon rounds we signed.
+#[derive(Deserialize)]
+struct SessionSnapshotV3 {
+    version: u32,
+    node: Vec<u8>,
+    params: ThresholdParams,
+    parts: BTreeMap<usize, Part>,
+    acks: Vec<(usize, Ack)>,
+    pub_key_set: Option<PublicKeySet>,
+    key_share: Option<Vec<u8>>,
+    faults: Vec<Fault<usize>>,
+}
+
+impl From<SessionSnapshotV3> for SessionSnapshot {
+    fn from(snapshot: SessionSnapshotV3) -> Self {
+        SessionSnapshot {
+            version: SESSION_SNAPSHOT_VERSION,
+            node: snapshot.node,
+            params: snapshot.params,
+            parts: snapshot.parts,
+            acks: snapshot.acks,
+            pub_key_set: snapshot.pub_key_set,
+            key_share: snapshot.key_share,
+            faults: snapshot.faults,
+            beacon_round: None,
+        }
+    }
+}
+
+/// The rest of a `SessionSnapshot` in the unversioned format, after the `KeyGenSnapshot`. Later
+/// snapshots in that format are followed by the faults.
+#[derive(Deserialize)]
+struct LegacySessionSnapshot {
+    params: ThresholdParams,
+    parts: BTreeMap<usize, Part>,
+    acks: Vec<(usize, Ack)>,
+    pub_key_set: Option<PublicKeySet>,
+    key_share: Option<Vec<u8>>,
+}
+
+impl SessionSnapshot {
+    /// Deserializes a snapshot, after checking its format version. Snapshots in the unversioned
+    /// format are migrated.
+    fn from_bytes(bytes: &[u8]) -> Result<SessionSnapshot, dkg::Error> {
+        let version: u32 = bincode::deserialize(bytes)?;
+        match version {
+            1 | 2 => {
+                let mut rest = bytes;
+                let node = KeyGenSnapshot::<usize>::read(&mut rest)?;
+                let with_faults = bincode::deserialize::<(LegacySessionSnapshot, _)>(rest);
+                let (legacy, faults) = match with_faults {
+                    Ok((legacy, faults)) => (legacy, faults),
+                    Err(_) => (bincode::deserialize(rest)?, vec![]),
+                };
+                Ok(SessionSnapshot {
+                    version: SESSION_SNAPSHOT_VERSION,
+                    node: bincode::serialize(&node)?,
+                    params: legacy.params,
+                    parts: legacy.parts,
+                    acks: legacy.acks,
+                    pub_key_set: legacy.pub_key_set,
+                    key_share: legacy.key_share,
+                    faults,
+                    beacon_round: None,
+                })
+            }
+            3 => Ok(bincode::deserialize::<SessionSnapshotV3>(bytes)?.into()),
+            SESSION_SNAPSHOT_VERSION => Ok(bincode::deserialize(bytes)?),
+            _ => Err(dkg::Error::SnapshotVersion(version)),
+        }
+    }
 }
 
 type Db = Arc<RwLock<HashMap<usize, Session>>>;
 
+/// The state shared by all handlers of this node.
+#[derive(Debug, Clone)]
+struct AppState {
+    /// Our node ID in every committee we join.
+    node_id: usize,
+    /// Our long-term encryption key. `Part` rows and `Ack` values for us are encrypted to it.
+    sk: SecretKey,
+    /// The smallest `threshold + 1` we accept for a key we hold a share of.
+    min_quorum: usize,
+    /// The smallest number of complete parts we accept to generate a key from, if some members
+    /// missed a deadline. At least `threshold + 1` parts are always required, which is the
+    /// default.
+    min_complete: usize,
+    /// When the beacon rounds are due. We sign no beacon rounds unless `BEACON_GENESIS` is set.
+    beacon: Option<BeaconSchedule>,
+    /// The recipients we encrypt decryption shares to with `/decrypt_share_for`. None, unless
+    /// `DECRYPT_FOR_RECIPIENTS` lists them.
+    recipients: Vec<threshold_crypto::PublicKey>,
+    /// DKG sessions, by session ID.
+    db: Db,
+    /// Where the sessions are persisted, if `STATE_DIR` is set.
+    store: Option<Store>,
+}
+
+type HandlerError = (StatusCode, String);
+
 #[tokio::main]
 async fn main() {
     tracing_subscriber::fmt()
         .with_max_level(tracing::Level::DEBUG)
         .init();
 
+    let node_id = env_or("NODE_ID", 0);
+    let port = env_or("PORT", 3000);
+    // With a state directory, our key and sessions survive restarts. Otherwise they are lost.
+    let store = std::env::var("STATE_DIR")
+        .ok()
+        .map(|dir| Store::open(dir).expect("Could not open the state directory."));
+    let sk = match &store {
+        Some(store) => store.node_key().expect("Could not load the node key."),
+        None => rand::random(),
+    };
     let db = Db::default();
+    if let Some(store) = &store {
+        let sessions = store.load_sessions().expect("Could not load the sessions.");
+        // A session we can't restore is reported and skipped, but doesn't stop the others.
+        for (session_id, ser_session) in sessions {
+            match restore_session(&ser_session, &sk) {
+                Ok(session) => {
+                    tracing::debug!("node #{} resumes session #{}", node_id, session_id);
+                    db.write().unwrap().insert(session_id, session);
+                }
+                Err(err) => tracing::error!(
+                    "node #{} can't restore session #{}: {}",
+                    node_id,
+                    session_id,
+                    err
+                ),
+            }
+        }
+    }
+    // The beacon rounds are due from `BEACON_GENESIS`, in seconds since the Unix epoch, once per
+    // `BEACON_PERIOD_SECS`.
+    let beacon = std::env::var("BEACON_GENESIS")
+        .ok()
+        .map(|genesis| BeaconSchedule {
+            genesis: genesis.parse().expect("Invalid BEACON_GENESIS."),
+            period: env_or("BEACON_PERIOD_SECS", DEFAULT_BEACON_PERIOD_SECS),
+        });
+    // A JSON list of public keys, in the format of `/pub_key`.
+    let recipients = std::env::var("DECRYPT_FOR_RECIPIENTS")
+        .map(|list| serde_json::from_str(&list).expect("Invalid DECRYPT_FOR_RECIPIENTS."))
+        .unwrap_or_default();
+    let state = AppState {
+        node_id,
+        sk,
+        min_quorum: env_or("MIN_QUORUM", DEFAULT_MIN_QUORUM),
+        min_complete: env_or("MIN_COMPLETE", 0),
+        beacon,
+        recipients,
+        db,
+        store,
+    };
 
     // Compose the routes
     let app = Router::new()
+        .route("/pub_key", post(pub_key))
         .route("/init_dkg", post(init_dkg))
+        .route("/observe", post(observe))
         .route("/commit", post(commit))
+        .route("/justify", post(justify))
+        .route("/judge", post(judge))
+        .route("/ack", post(ack))
         .route("/finalize_dkg", post(finalize_dkg))
+        .route("/init_refresh", post(init_refresh))
+        .route("/commit_refresh", post(commit_refresh))
+        .route("/init_reshare", post(init_reshare))
+        .route("/transcript", post(transcript))
+        .route("/encrypt", post(encrypt))
+        .route("/encrypt_aead", post(encrypt_aead))
+        .route("/decrypt_share", post(decrypt_share))
+        .route("/decrypt_share_batch", post(decrypt_share_batch))
+        .route("/decrypt_share_for", post(decrypt_share_for))
+        .route("/encrypt_labelled", post(encrypt_labelled))
+        .route("/decrypt_share_labelled", post(decrypt_share_labelled))
+        .route("/encrypt_envelope", post(encrypt_envelope))
+        .route("/decrypt_share_envelope", post(decrypt_share_envelope))
+        .route("/sign_share", post(sign_share))
+        .route("/sign_share_batch", post(sign_share_batch))
+        .route("/beacon_share", post(beacon_share))
         // Add middleware to all routes
         .layer(
             ServiceBuilder::new()
@@ -58,196 +276,1417 @@ async fn main() {
                 .layer(TraceLayer::new_for_http())
                 .into_inner(),
         )
-        .with_state(db);
+        .with_state(state);
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    tracing::debug!("listening on {}", addr);
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    tracing::debug!("node #{} listening on {}", node_id, addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
         .await
         .unwrap();
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct PubKeyResp {
+    node_id: usize,
+    pk: threshold_crypto::PublicKey,
+}
+
+#[debug_handler]
+async fn pub_key(State(state): State<AppState>) -> impl IntoResponse {
+    Json(PubKeyResp {
+        node_id: state.node_id,
+        pk: state.sk.public_key(),
+    })
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct InitDkgReq {
-    p1_pk: threshold_crypto::PublicKey,
+    session_id: usize,
+    /// Any `threshold + 1` of the `num_nodes` key shares will be able to sign or decrypt.
+    threshold: usize,
+    num_nodes: usize,
+    pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
 }
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct InitDkgResp {
-    p0_pk: threshold_crypto::PublicKey,
-    p0_part: Part,
+    part: Part,
 }
+
 #[debug_handler]
-async fn init_dkg(State(db): State<Db>, Json(req_body): Json<InitDkgReq>) -> impl IntoResponse {
+async fn init_dkg(
+    State(state): State<AppState>,
+    Json(req_body): Json<InitDkgReq>,
+) -> Result<Json<InitDkgResp>, HandlerError> {
     print_json(&req_body, "init req body");
+    check_new_session(&state.db, req_body.session_id)?;
 
-    // Create public key with random secret
-    let sk: SecretKey = rand::random();
-    let p0_pk = sk.public_key();
-
-    // Get client public key from request body, create a map of public keys
-    let mut map = BTreeMap::new();
-    map.insert(0, p0_pk.clone());
-    map.insert(1, req_body.p1_pk.clone());
-    let pub_keys: PubKeyMap<usize, threshold_crypto::PublicKey> = Arc::new(map);
+    // The committee must list us with the key we actually hold, otherwise nobody can send us rows.
+    if req_body.pub_keys.get(&state.node_id) != Some(&state.sk.public_key()) {
+        return Err(bad_request(format!(
+            "Node #{} is not a member of the committee",
+            state.node_id
+        )));
+    }
+    let pub_keys: PubKeyMap<usize, threshold_crypto::PublicKey> = Arc::new(req_body.pub_keys);
 
-    // Create SyncKeyGen instance
+    // Create SyncKeyGen instance, refusing keys that too few shares could reconstruct
+    let params = ThresholdParams::new(req_body.threshold, req_body.num_nodes);
     let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
-    let threshold = 0;
-    let (sync_key_gen, opt_part) =
-        SyncKeyGen::new(0, sk.clone(), pub_keys.clone(), threshold, &mut rng)
-            .unwrap_or_else(|_| panic!("Failed to create `SyncKeyGen` instance for node #{}", 0));
-
-    let parts = vec![opt_part.unwrap().clone()];
-    let acks = vec![];
+    let (sync_key_gen, opt_part) = SyncKeyGen::with_params(
+        state.node_id,
+        state.sk.clone(),
+        pub_keys,
+        params,
+        state.min_quorum,
+        &mut rng,
+    )
+    .map_err(bad_request)?;
+    let part = opt_part.expect("We are not an observer, so we should send a Part.");
 
     let session = Session {
-        sk,
-        node: Arc::new(Mutex::new(sync_key_gen)),
-        parts: parts.clone(),
-        acks,
+        node: Arc::new(Mutex::new(AsyncKeyGen::from(sync_key_gen))),
+        params,
+        parts: BTreeMap::new(),
+        acks: vec![],
+        pub_key_set: None,
+        key_share: None,
+        faults: vec![],
+        beacon_round: None,
     };
+    insert_session(&state.db, req_body.session_id, session)?;
+    save_session(&state, req_body.session_id).await?;
+
+    let resp = InitDkgResp { part };
+    print_json(&resp, "init resp");
+    Ok(Json(resp))
+}
 
-    db.write().unwrap().insert(0, session);
+/// Joins a session as an observer. Observers receive every part, ack and complaint, validate what
+/// they can, and end up with the public key set, but no key share. They don't send any messages.
+async fn observe(
+    State(state): State<AppState>,
+    Json(req_body): Json<InitDkgReq>,
+) -> Result<Json<()>, HandlerError> {
+    print_json(&req_body, "observe req body");
+    check_new_session(&state.db, req_body.session_id)?;
 
-    let resp = InitDkgResp {
-        p0_pk: p0_pk.clone(),
-        p0_part: parts[0].clone(),
+    if req_body.pub_keys.contains_key(&state.node_id) {
+        return Err(bad_request(format!(
+            "Node #{} is a member of the committee, not an observer",
+            state.node_id
+        )));
+    }
+    let pub_keys: PubKeyMap<usize, threshold_crypto::PublicKey> = Arc::new(req_body.pub_keys);
+
+    // Create SyncKeyGen instance without a part, since we are not in `pub_keys`
+    let params = ThresholdParams::new(req_body.threshold, req_body.num_nodes);
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let (sync_key_gen, opt_part) = SyncKeyGen::with_params(
+        state.node_id,
+        state.sk.clone(),
+        pub_keys,
+        params,
+        state.min_quorum,
+        &mut rng,
+    )
+    .map_err(bad_request)?;
+    assert!(opt_part.is_none(), "Observers don't send a Part.");
+
+    let session = Session {
+        node: Arc::new(Mutex::new(AsyncKeyGen::from(sync_key_gen))),
+        params,
+        parts: BTreeMap::new(),
+        acks: vec![],
+        pub_key_set: None,
+        key_share: None,
+        faults: vec![],
+        beacon_round: None,
     };
+    insert_session(&state.db, req_body.session_id, session)?;
+    save_session(&state, req_body.session_id).await?;
 
-    print_json(&resp, "init resp");
-    Json(resp)
+    Ok(Json(()))
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct CommitReq {
-    p1_part: Part,
-    p1_acks: Vec<Ack>,
+    session_id: usize,
+    /// The `Part` of every member, by sender ID.
+    parts: BTreeMap<usize, Part>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct CommitResp {
-    p0_acks: Vec<Ack>,
+    acks: Vec<Ack>,
+    /// Our complaints against invalid parts, by accused sender ID.
+    complaints: Vec<(usize, Complaint)>,
+    /// The faults we detected in the parts, and in the acks that were buffered for them.
+    faults: Vec<Fault<usize>>,
 }
 
-async fn commit(State(db): State<Db>, Json(req_body): Json<CommitReq>) -> impl IntoResponse {
+async fn commit(
+    State(state): State<AppState>,
+    Json(req_body): Json<CommitReq>,
+) -> Result<Json<CommitResp>, HandlerError> {
     print_json(&req_body, "commit req body");
 
-    let session = db.read().unwrap().get(&0).cloned().unwrap();
-    let arc_node = session.node.clone();
-    let mut node = arc_node.try_lock().unwrap();
+    let session = get_session(&state.db, req_body.session_id)?;
+    let mut node = session.node.lock().await;
+    let resp = handle_parts(
+        state.node_id,
+        &mut node,
+        &req_body.parts,
+        |node, id, part, rng| node.handle_part(id, part, rng),
+    )?;
 
-    let mut parts = session.parts.clone();
-    parts.insert(1, req_body.p1_part.clone());
+    update_session(&state.db, req_body.session_id, |session| {
+        session.parts = req_body.parts;
+        session.faults.extend(resp.faults.iter().cloned());
+    });
+    drop(node);
+    save_session(&state, req_body.session_id).await?;
+
+    print_json(&resp, "commit resp");
+    Ok(Json(resp))
+}
 
+/// Handles the part of every member, in ascending ID order, and returns our `Ack`s, complaints
+/// and the faults we detected.
+fn handle_parts<P, F>(
+    node_id: usize,
+    node: &mut AsyncKeyGen<usize>,
+    parts: &BTreeMap<usize, P>,
+    mut handle_part: F,
+) -> Result<CommitResp, HandlerError>
+where
+    P: Borrow<Part> + Clone,
+    F: FnMut(
+        &mut AsyncKeyGen<usize>,
+        &usize,
+        P,
+        &mut rand::rngs::OsRng,
+    ) -> Result<(PartOutcome, Vec<(usize, Ack, AckFault)>), dkg::Error>,
+{
     let mut acks = vec![];
+    let mut complaints = vec![];
+    let mut faults = vec![];
     let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    for (id, part) in parts.iter() {
+        let (outcome, ack_faults) =
+            handle_part(node, id, part.clone(), &mut rng).map_err(bad_request)?;
+        for (sender_id, ack, fault) in ack_faults {
+            println!(
+                "Node #{} handles a buffered Ack from node #{} and detects a fault: {:?}",
+                node_id, sender_id, fault
+            );
+            let kind = FaultKind::Ack(fault);
+            faults.push(Fault::new(sender_id, kind, &ack, Phase::Commit).map_err(internal_error)?);
+        }
+        match outcome {
+            PartOutcome::Valid(Some(ack)) => acks.push(ack),
+            PartOutcome::Invalid(fault) => {
+                println!(
+                    "Node #{} handles Part from node #{} and detects a fault: {:?}",
+                    node_id, id, fault
+                );
+                let kind = FaultKind::Part(fault);
+                let part: &Part = part.borrow();
+                let fault = Fault::new(*id, kind, part, Phase::Commit);
+                faults.push(fault.map_err(internal_error)?);
+                // Only members can complain. The members detect the same faults anyway.
+                if !node.is_observer() {
+                    let complaint = node.complain(id, part.clone()).map_err(bad_request)?;
+                    complaints.push((*id, complaint));
+                }
+            }
+            // We are an observer, or we have handled the same part before.
+            PartOutcome::Valid(None) => (),
+        }
+    }
+    Ok(CommitResp {
+        acks,
+        complaints,
+        faults,
+    })
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JustifyReq {
+    session_id: usize,
+    accuser_id: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JustifyResp {
+    justification: Option<Justification>,
+}
+
+async fn justify(
+    State(state): State<AppState>,
+    Json(req_body): Json<JustifyReq>,
+) -> Result<Json<JustifyResp>, HandlerError> {
+    print_json(&req_body, "justify req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let node = session.node.lock().await;
+    let justification = node.justify(&req_body.accuser_id).map_err(bad_request)?;
+
+    Ok(Json(JustifyResp { justification }))
+}
 
-    for (id, part) in parts.clone().iter().enumerate() {
-        // We only have 2 participants
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgedComplaint {
+    accuser_id: usize,
+    accused_id: usize,
+    complaint: Complaint,
+    /// The accused proposer's answer, or `None` if it didn't answer.
+    justification: Option<Justification>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgeReq {
+    session_id: usize,
+    /// All complaints, in the order they must be judged.
+    complaints: Vec<JudgedComplaint>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct JudgeResp {
+    /// The `Ack`s for parts we complained about, but that turned out to be valid.
+    acks: Vec<Ack>,
+    /// The proposers whose part is discarded.
+    disqualified: Vec<usize>,
+    /// The proposers we disqualified because of an upheld complaint.
+    faults: Vec<Fault<usize>>,
+}
+
+async fn judge(
+    State(state): State<AppState>,
+    Json(req_body): Json<JudgeReq>,
+) -> Result<Json<JudgeResp>, HandlerError> {
+    print_json(&req_body, "judge req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let mut node = session.node.lock().await;
+
+    let mut acks = vec![];
+    let mut faults = vec![];
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    for judged in req_body.complaints {
+        // The accused proposer is the one whose part the complaint contains.
+        let proposer_idx = judged.complaint.proposer_idx();
+        let accused_id = node
+            .proposer_id(proposer_idx)
+            .filter(|id| *id == judged.accused_id)
+            .ok_or_else(|| {
+                bad_request(format!(
+                    "Node #{} accuses node #{}, but complains about the part of proposer {}",
+                    judged.accuser_id, judged.accused_id, proposer_idx
+                ))
+            })?;
+        let kind = FaultKind::ComplaintUpheld;
+        let fault = Fault::new(accused_id, kind, &judged.complaint, Phase::Judge)
+            .map_err(internal_error)?;
         match node
-            .handle_part(&id, part.clone(), &mut rng)
-            .expect("Failed to handle Part")
+            .handle_complaint(
+                &judged.accuser_id,
+                judged.complaint,
+                judged.justification,
+                &mut rng,
+            )
+            .map_err(bad_request)?
         {
-            PartOutcome::Valid(Some(ack)) => acks.push(ack),
-            PartOutcome::Invalid(fault) => panic!(
-                "Node #0 handles Part from node #{} and detects a fault: {:?}",
-                id, fault
-            ),
-            PartOutcome::Valid(None) => {
-                panic!("We are not an observer, so we should send Ack.")
+            ComplaintOutcome::Upheld => {
+                println!(
+                    "Node #{} upholds the complaint of node #{} against node #{}",
+                    state.node_id, judged.accuser_id, accused_id
+                );
+                faults.push(fault);
             }
+            ComplaintOutcome::Dismissed(opt_ack) => acks.extend(opt_ack),
         }
     }
 
-    for ack in req_body.p1_acks.into_iter() {
-        acks.push(ack);
-    }
-    let resp_acks = acks.clone();
+    let disqualified = node.disqualified_nodes();
+    update_session(&state.db, req_body.session_id, |session| {
+        session.faults.extend(faults.iter().cloned())
+    });
+    drop(node);
+    save_session(&state, req_body.session_id).await?;
 
-    let updated_session = Session {
-        sk: session.sk.clone(),
-        node: session.node.clone(),
-        parts,
+    let resp = JudgeResp {
         acks,
+        disqualified,
+        faults,
     };
+    print_json(&resp, "judge resp");
+    Ok(Json(resp))
+}
 
-    db.write().unwrap().insert(0, updated_session);
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct AckReq {
+    session_id: usize,
+    /// `Ack`s together with their sender ID, in any order.
+    acks: Vec<(usize, Ack)>,
+}
 
-    let resp = CommitResp { p0_acks: resp_acks };
-    print_json(&resp, "commit resp");
-    Json(resp)
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct AckResp {
+    /// The parts that are complete from our point of view.
+    qualified: QualifiedSet,
+}
+
+async fn ack(
+    State(state): State<AppState>,
+    Json(req_body): Json<AckReq>,
+) -> Result<Json<AckResp>, HandlerError> {
+    print_json(&req_body, "ack req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let mut node = session.node.lock().await;
+
+    // `Ack`s for parts we haven't handled yet are buffered by the node.
+    let mut faults = vec![];
+    for (sender_id, ack) in req_body.acks.iter() {
+        match node
+            .handle_ack(sender_id, ack.clone())
+            .map_err(bad_request)?
+        {
+            Some(AckOutcome::Valid) | None => (),
+            Some(AckOutcome::Invalid(fault)) => {
+                println!(
+                    "Node #{} handles Ack from node #{} and detects a fault: {:?}",
+                    state.node_id, sender_id, fault
+                );
+                let kind = FaultKind::Ack(fault);
+                let fault = Fault::new(*sender_id, kind, ack, Phase::Ack);
+                faults.push(fault.map_err(internal_error)?);
+            }
+        }
+    }
+
+    let qualified = node.qualified_set();
+    update_session(&state.db, req_body.session_id, |session| {
+        session.acks.extend(req_body.acks);
+        session.faults.extend(faults);
+    });
+    drop(node);
+    save_session(&state, req_body.session_id).await?;
+
+    let resp = AckResp { qualified };
+    print_json(&resp, "ack resp");
+    Ok(Json(resp))
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct FinalizeReq {
-    sig_share_1: SignatureShare,
-    signed_msg_1: String,
+    session_id: usize,
+    /// The parts the key is generated from, agreed on by all members.
+    qualified: QualifiedSet,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 struct FinalizeResp {
-    is_success: bool,
+    pub_key_set: PublicKeySet,
+    /// Our signature share, or `None` if we are an observer.
+    sig_share: Option<SignatureShare>,
+    /// All faults we detected in this key generation.
+    faults: Vec<Fault<usize>>,
+    /// The members whose `Part` or `Ack`s we never received, e.g. because they missed a deadline.
+    absent: BTreeSet<usize>,
 }
+
 async fn finalize_dkg(
-    State(db): State<Db>,
+    State(state): State<AppState>,
     Json(req_body): Json<FinalizeReq>,
-) -> impl IntoResponse {
-    let req_body_json = match serde_json::to_string(&req_body) {
-        Ok(share) => share,
-        Err(e) => {
-            println!("Error: {}", e);
-            "".to_string()
+) -> Result<Json<FinalizeResp>, HandlerError> {
+    print_json(&req_body, "finalize req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let node = session.node.lock().await;
+
+    // Like the orchestrator, we require `threshold + 1` parts unless `MIN_COMPLETE` is higher.
+    let min_complete = node.min_parts().max(state.min_complete);
+    if req_body.qualified.len() < min_complete {
+        return Err(bad_request(format!(
+            "Only {} parts are complete, {} are required",
+            req_body.qualified.len(),
+            min_complete
+        )));
+    }
+    // Fails if the qualified set is too small, or if we didn't handle enough `Ack`s yet.
+    let (pub_key_set, opt_key_share) = node
+        .generate_key_share(&req_body.qualified)
+        .map_err(bad_request)?;
+    if pub_key_set.threshold() != session.params.threshold {
+        return Err(internal_error(format!(
+            "The key has threshold {}, expected {}",
+            pub_key_set.threshold(),
+            session.params.threshold
+        )));
+    }
+    // A refresh must keep the master key.
+    if let Some(old_pub_key_set) = &session.pub_key_set {
+        if pub_key_set.public_key() != old_pub_key_set.public_key() {
+            return Err(internal_error("The refresh changed the master public key"));
         }
+    }
+    // We only ever sign the fixed confirmation of this session and key.
+    let confirmation =
+        dkg::dkg_confirmation(req_body.session_id as u64, &pub_key_set).map_err(internal_error)?;
+    let sig_share = opt_key_share
+        .as_ref()
+        .map(|key_share| key_share.sign(SigningContext::DkgConfirm, &confirmation));
+    let absent = node
+        .missing_parts()
+        .into_iter()
+        .chain(node.missing_acks())
+        .collect();
+
+    update_session(&state.db, req_body.session_id, |session| {
+        session.pub_key_set = Some(pub_key_set.clone());
+        session.key_share = opt_key_share;
+    });
+    drop(node);
+    save_session(&state, req_body.session_id).await?;
+
+    let resp = FinalizeResp {
+        pub_key_set,
+        sig_share,
+        faults: session.faults,
+        absent,
     };
-    println!("req_body {:?}", req_body_json);
-
-    let session = db.read().unwrap().get(&0).cloned().unwrap();
-    // let session = get_m(0);
-
-    let arc_node = session.node.clone();
-    let mut node = arc_node.try_lock().unwrap();
-    let acks = session.acks.clone();
-
-    // we handle all the `Ack`s.
-    for ack in acks {
-        for id in 0..1 {
-            match node
-                .handle_ack(&id, ack.clone())
-                .expect("Failed to handle Ack")
-            {
-                AckOutcome::Valid => (),
-                AckOutcome::Invalid(fault) => panic!("Invalid Ack: {:?}", fault),
-            }
-        }
+    print_json(&resp, "finalize resp");
+    Ok(Json(resp))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitRefreshReq {
+    session_id: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitRefreshResp {
+    /// Our part, or `None` if we are an observer.
+    part: Option<RefreshPart>,
+}
+
+/// Starts a refresh of our key share in a finalized session. The session then goes through the
+/// same phases as a new key, starting with `/commit_refresh`, and `/finalize_dkg` replaces our
+/// key share. The old share stays in use until then.
+async fn init_refresh(
+    State(state): State<AppState>,
+    Json(req_body): Json<InitRefreshReq>,
+) -> Result<Json<InitRefreshResp>, HandlerError> {
+    print_json(&req_body, "init refresh req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session.pub_key_set.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "Session #{} has no key to refresh",
+            req_body.session_id
+        ))
+    })?;
+    let pub_keys = session.node.lock().await.public_keys().clone();
+
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    // Observers follow the refresh without a key share. Members fail without theirs.
+    let (refresh, part) = AsyncKeyGen::refresh(
+        state.node_id,
+        state.sk.clone(),
+        pub_keys,
+        pub_key_set,
+        session.key_share.as_ref(),
+        &mut rng,
+    )
+    .map_err(bad_request)?;
+
+    update_session(&state.db, req_body.session_id, |session| {
+        session.node = Arc::new(Mutex::new(refresh));
+        session.parts = BTreeMap::new();
+        session.acks = vec![];
+        session.faults = vec![];
+    });
+    save_session(&state, req_body.session_id).await?;
+
+    let resp = InitRefreshResp { part };
+    print_json(&resp, "init refresh resp");
+    Ok(Json(resp))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitReshareReq {
+    /// The session of the new key.
+    session_id: usize,
+    /// The session of the current key, if we hold a share of it.
+    old_session_id: usize,
+    /// Any `threshold + 1` of the `num_nodes` new key shares will be able to sign or decrypt.
+    threshold: usize,
+    num_nodes: usize,
+    /// The public keys of the new committee, by node ID.
+    pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
+    /// The public keys of the current share holders, by node ID.
+    old_pub_keys: BTreeMap<usize, threshold_crypto::PublicKey>,
+    /// The current key.
+    pub_key_set: PublicKeySet,
+    /// The IDs of the observers of the new key.
+    #[serde(default)]
+    observers: BTreeSet<usize>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct InitReshareResp {
+    /// Our part, if we hold a share of the current key.
+    part: Option<RefreshPart>,
+}
+
+/// Starts resharing a key to a new committee. The current share holders deal their shares, and
+/// the new session goes through the same phases as a new key, starting with `/commit_refresh`.
+/// A share holder that is not in the new committee only answers complaints, and an observer only
+/// validates the messages.
+async fn init_reshare(
+    State(state): State<AppState>,
+    Json(req_body): Json<InitReshareReq>,
+) -> Result<Json<InitReshareResp>, HandlerError> {
+    print_json(&req_body, "init reshare req body");
+    if req_body.session_id == req_body.old_session_id {
+        return Err(bad_request("The reshared key needs a new session ID"));
+    }
+    check_new_session(&state.db, req_body.session_id)?;
+
+    let our_pk = Some(state.sk.public_key());
+    let is_member = req_body.pub_keys.get(&state.node_id) == our_pk.as_ref();
+    let is_dealer = req_body.old_pub_keys.get(&state.node_id) == our_pk.as_ref();
+    let is_observer = req_body.observers.contains(&state.node_id);
+    if is_observer && req_body.pub_keys.contains_key(&state.node_id) {
+        return Err(bad_request(format!(
+            "Node #{} is a member of the committee, not an observer",
+            state.node_id
+        )));
+    }
+    if !is_member && !is_dealer && !is_observer {
+        return Err(bad_request(format!(
+            "Node #{} is neither a current share holder, a new member nor an observer",
+            state.node_id
+        )));
+    }
+    let params = ThresholdParams::new(req_body.threshold, req_body.num_nodes);
+    if params.num_nodes != req_body.pub_keys.len() {
+        return Err(bad_request(dkg::Error::InvalidThreshold(
+            params.threshold,
+            req_body.pub_keys.len(),
+        )));
     }
+    params.validate(state.min_quorum).map_err(bad_request)?;
+
+    // As a current share holder, we deal our share of the key.
+    let key_share = if is_dealer {
+        let old_session = get_session(&state.db, req_body.old_session_id)?;
+        if old_session.pub_key_set.as_ref() != Some(&req_body.pub_key_set) {
+            return Err(bad_request(format!(
+                "Session #{} doesn't hold the key to reshare",
+                req_body.old_session_id
+            )));
+        }
+        old_session.key_share
+    } else {
+        None
+    };
+
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let (reshare, part) = AsyncKeyGen::reshare(
+        state.node_id,
+        state.sk.clone(),
+        Arc::new(req_body.pub_keys),
+        params.threshold,
+        &Arc::new(req_body.old_pub_keys),
+        &req_body.pub_key_set,
+        key_share.as_ref(),
+        &mut rng,
+    )
+    .map_err(bad_request)?;
+
+    let session = Session {
+        node: Arc::new(Mutex::new(reshare)),
+        params,
+        parts: BTreeMap::new(),
+        acks: vec![],
+        pub_key_set: None,
+        key_share: None,
+        faults: vec![],
+        beacon_round: None,
+    };
+    insert_session(&state.db, req_body.session_id, session)?;
+    save_session(&state, req_body.session_id).await?;
+
+    let resp = InitReshareResp { part };
+    print_json(&resp, "init reshare resp");
+    Ok(Json(resp))
+}
 
-    let pub_key_set = node
-        .generate()
-        .expect("Failed to create `PublicKeySet` from node #0")
-        .0;
-    assert!(node.is_ready());
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct CommitRefreshReq {
+    session_id: usize,
+    /// The `RefreshPart` of every member, or in a resharing of every current share holder, by
+    /// sender ID.
+    parts: BTreeMap<usize, RefreshPart>,
+}
 
-    let (pks, opt_sks) = node.generate().unwrap_or_else(|_| {
-        panic!("Failed to create `PublicKeySet` and `SecretKeyShare` for node #0")
+async fn commit_refresh(
+    State(state): State<AppState>,
+    Json(req_body): Json<CommitRefreshReq>,
+) -> Result<Json<CommitResp>, HandlerError> {
+    print_json(&req_body, "commit refresh req body");
+
+    let session = get_session(&state.db, req_body.session_id)?;
+    let mut node = session.node.lock().await;
+    let resp = handle_parts(
+        state.node_id,
+        &mut node,
+        &req_body.parts,
+        |node, id, part, rng| node.handle_refresh_part(id, part, rng),
+    )?;
+
+    update_session(&state.db, req_body.session_id, |session| {
+        session.parts = req_body
+            .parts
+            .iter()
+            .map(|(id, part)| (*id, Borrow::<Part>::borrow(part).clone()))
+            .collect();
+        session.faults.extend(resp.faults.iter().cloned());
     });
-    assert_eq!(pks, pub_key_set); // All nodes now know the public keys and public key shares.
-
-    let sks_0 = opt_sks.expect("Not an observer node: We receive a secret key share.");
-    let sig_share_0 = sks_0.sign(req_body.signed_msg_1.clone());
-    let mut sig_shares: BTreeMap<usize, SignatureShare> = BTreeMap::new();
-    sig_shares.insert(0, sig_share_0);
-    sig_shares.insert(1, req_body.sig_share_1);
-    let combine_sig = pub_key_set
-        .combine_signatures(&sig_shares)
-        .expect("The shares can be combined.");
-
-    let is_success = pub_key_set
+    drop(node);
+    save_session(&state, req_body.session_id).await?;
+
+    print_json(&resp, "commit refresh resp");
+    Ok(Json(resp))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct TranscriptReq {
+    session_id: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct TranscriptResp {
+    /// Every `Part`, `Ack` and complaint we handled, in order and hash-chained.
+    transcript: Transcript,
+}
+
+/// Returns our transcript of the session. It only contains public messages, so anyone can check
+/// that the key was generated from it, with `Transcript::verify`.
+async fn transcript(
+    State(state): State<AppState>,
+    Json(req_body): Json<TranscriptReq>,
+) -> Result<Json<TranscriptResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let transcript = session.node.lock().await.transcript().clone();
+    Ok(Json(TranscriptResp { transcript }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptReq {
+    /// The session of the key to encrypt to.
+    session_id: usize,
+    plaintext: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptResp {
+    ciphertext: Ciphertext,
+}
+
+/// Encrypts the plaintext to the master public key of a finalized session. Any `threshold + 1`
+/// members can decrypt it together.
+async fn encrypt(
+    State(state): State<AppState>,
+    Json(req_body): Json<EncryptReq>,
+) -> Result<Json<EncryptResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let ciphertext = pub_key_set
         .public_key()
-        .verify(&combine_sig, req_body.signed_msg_1);
-    println!("is_success {:?}", is_success);
+        .encrypt_with_rng(&mut rng, &req_body.plaintext);
+    Ok(Json(EncryptResp { ciphertext }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptAeadReq {
+    /// The session of the key to encrypt to.
+    session_id: usize,
+    plaintext: Vec<u8>,
+    /// Data that is authenticated but not encrypted. It must be supplied again to decrypt.
+    #[serde(default)]
+    associated_data: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptAeadResp {
+    ciphertext: HybridCiphertext,
+}
+
+/// Encrypts the plaintext with a fresh data key, and the data key to the master public key of a
+/// finalized session. Decrypting only needs the decryption shares of the data key ciphertext.
+async fn encrypt_aead(
+    State(state): State<AppState>,
+    Json(req_body): Json<EncryptAeadReq>,
+) -> Result<Json<EncryptAeadResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let ciphertext = HybridCiphertext::encrypt(
+        &pub_key_set.public_key(),
+        &req_body.plaintext,
+        &req_body.associated_data,
+        &mut rng,
+    )
+    .map_err(internal_error)?;
+    Ok(Json(EncryptAeadResp { ciphertext }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: Ciphertext,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    dec_share: DecryptionShare,
+}
+
+/// Returns our decryption share of a ciphertext encrypted to the key of a finalized session.
+async fn decrypt_share(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptShareReq>,
+) -> Result<Json<DecryptShareResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    let node_idx = share_index(&state, &session).await?;
+    // Fails unless the ciphertext is well formed, i.e. was encrypted to some key.
+    let dec_share = key_share
+        .secret_key_share()
+        .decrypt_share(&req_body.ciphertext)
+        .ok_or_else(|| bad_request("Invalid ciphertext"))?;
+    Ok(Json(DecryptShareResp {
+        node_idx,
+        dec_share,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareBatchReq {
+    /// The session of the key the ciphertexts were encrypted to.
+    session_id: usize,
+    ciphertexts: Vec<Ciphertext>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareBatchResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    /// Our decryption share of each ciphertext, in the same order, or the reason why there is
+    /// none.
+    dec_shares: Vec<Result<DecryptionShare, String>>,
+}
+
+/// Returns our decryption shares of up to `MAX_BATCH_SIZE` ciphertexts encrypted to the key of a
+/// finalized session. An invalid ciphertext doesn't fail the other ones.
+async fn decrypt_share_batch(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptShareBatchReq>,
+) -> Result<Json<DecryptShareBatchResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    if req_body.ciphertexts.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(format!(
+            "At most {} ciphertexts per batch",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let node_idx = share_index(&state, &session).await?;
+    let secret_key_share = key_share.secret_key_share();
+    // Checking the ciphertexts takes pairings: it runs off the async runtime.
+    let ciphertexts = req_body.ciphertexts;
+    let dec_shares = tokio::task::spawn_blocking(move || {
+        ciphertexts
+            .iter()
+            .map(|ciphertext| {
+                secret_key_share
+                    .decrypt_share(ciphertext)
+                    .ok_or_else(|| "Invalid ciphertext".to_string())
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(internal_error)?;
+    Ok(Json(DecryptShareBatchResp {
+        node_idx,
+        dec_shares,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareForReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: Ciphertext,
+    /// The public key of the recipient, the only one who can combine the shares.
+    recipient: threshold_crypto::PublicKey,
+    /// The recipient's signature of `dkg::decrypt_for_request`.
+    recipient_sig: Signature,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareForResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    /// Our decryption share, encrypted to the recipient.
+    enc_share: Vec<u8>,
+}
+
+/// Returns our decryption share of a ciphertext, encrypted to the recipient's public key, so that
+/// the combiner and any relay can't decrypt the ciphertext themselves. The recipient must be one
+/// we know, and must have signed the request: otherwise the combiner could swap in its own key.
+async fn decrypt_share_for(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptShareForReq>,
+) -> Result<Json<DecryptShareForResp>, HandlerError> {
+    if !state.recipients.contains(&req_body.recipient) {
+        return Err(forbidden("Unknown recipient"));
+    }
+    let request = dkg::decrypt_for_request(
+        req_body.session_id as u64,
+        &req_body.ciphertext,
+        &req_body.recipient,
+    )
+    .map_err(internal_error)?;
+    if !req_body.recipient.verify(&req_body.recipient_sig, request) {
+        return Err(forbidden("The recipient didn't sign the request"));
+    }
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    let node_idx = share_index(&state, &session).await?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let enc_share = key_share
+        .decrypt_share_for(&req_body.ciphertext, &req_body.recipient, &mut rng)
+        .map_err(bad_request)?;
+    Ok(Json(DecryptShareForResp {
+        node_idx,
+        enc_share,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptLabelledReq {
+    /// The session of the key to encrypt to.
+    session_id: usize,
+    plaintext: Vec<u8>,
+    /// The context the ciphertext is bound to, e.g. a tenant or record ID.
+    label: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptLabelledResp {
+    ciphertext: LabelledCiphertext,
+}
+
+/// Encrypts the plaintext to the master public key of a finalized session, bound to the label.
+async fn encrypt_labelled(
+    State(state): State<AppState>,
+    Json(req_body): Json<EncryptLabelledReq>,
+) -> Result<Json<EncryptLabelledResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let ciphertext =
+        LabelledCiphertext::encrypt(&pub_key_set, &req_body.plaintext, &req_body.label, &mut rng)
+            .map_err(internal_error)?;
+    Ok(Json(EncryptLabelledResp { ciphertext }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareLabelledReq {
+    /// The session of the key the ciphertext was encrypted to.
+    session_id: usize,
+    ciphertext: LabelledCiphertext,
+    /// The label the ciphertext is expected to be bound to.
+    label: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareLabelledResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    dec_share: LabelledDecryptionShare,
+}
+
+/// Returns our decryption share of a labelled ciphertext, with a proof that it is valid. Refuses
+/// to issue it unless the ciphertext is bound to the expected label.
+async fn decrypt_share_labelled(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptShareLabelledReq>,
+) -> Result<Json<DecryptShareLabelledResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    let node_idx = share_index(&state, &session).await?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let dec_share = key_share
+        .decrypt_share_labelled(&req_body.ciphertext, &req_body.label, &mut rng)
+        .map_err(bad_request)?;
+    Ok(Json(DecryptShareLabelledResp {
+        node_idx,
+        dec_share,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptEnvelopeReq {
+    /// The session of the key to encrypt to.
+    session_id: usize,
+    plaintext: Vec<u8>,
+    #[serde(default)]
+    scheme: Scheme,
+    /// Data that is authenticated but not encrypted. Only the hybrid scheme supports it.
+    #[serde(default)]
+    associated_data: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct EncryptEnvelopeResp {
+    envelope: Envelope,
+}
+
+/// Encrypts the plaintext to the master public key of a finalized session, in an envelope that
+/// identifies the key by its fingerprint.
+async fn encrypt_envelope(
+    State(state): State<AppState>,
+    Json(req_body): Json<EncryptEnvelopeReq>,
+) -> Result<Json<EncryptEnvelopeResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let pub_key_set = session
+        .pub_key_set
+        .ok_or_else(|| bad_request(format!("Session #{} has no key yet", req_body.session_id)))?;
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let envelope = Envelope::seal(
+        req_body.scheme,
+        &pub_key_set,
+        &req_body.plaintext,
+        &req_body.associated_data,
+        &mut rng,
+    )
+    .map_err(bad_request)?;
+    Ok(Json(EncryptEnvelopeResp { envelope }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareEnvelopeReq {
+    /// The session whose key share to use, picked by the combiner, so that it verifies our share
+    /// against the right key set.
+    session_id: usize,
+    envelope: Envelope,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct DecryptShareEnvelopeResp {
+    /// The session whose key share we used.
+    session_id: usize,
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    dec_share: DecryptionShare,
+}
+
+/// Returns our decryption share of an envelope, using our share of the key it was encrypted to in
+/// the given session.
+async fn decrypt_share_envelope(
+    State(state): State<AppState>,
+    Json(req_body): Json<DecryptShareEnvelopeReq>,
+) -> Result<Json<DecryptShareEnvelopeResp>, HandlerError> {
+    // Fails unless we understand the envelope's version and scheme.
+    let ciphertext = req_body.envelope.key_ciphertext().map_err(bad_request)?;
+    let session_id = req_body.session_id;
+    let session = get_session(&state.db, session_id)?;
+    let pub_key_set = session.pub_key_set.as_ref();
+    if pub_key_set.and_then(|pks| envelope::fingerprint(pks).ok())
+        != Some(*req_body.envelope.key_fingerprint())
+    {
+        return Err(bad_request(format!(
+            "The envelope was not encrypted to the key of session #{}",
+            session_id
+        )));
+    }
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            session_id
+        ))
+    })?;
+    let node_idx = share_index(&state, &session).await?;
+    let dec_share = key_share
+        .secret_key_share()
+        .decrypt_share(&ciphertext)
+        .ok_or_else(|| bad_request("Invalid ciphertext"))?;
+    Ok(Json(DecryptShareEnvelopeResp {
+        session_id,
+        node_idx,
+        dec_share,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareReq {
+    /// The session of the key to sign with.
+    session_id: usize,
+    /// The context the signature is made in. Only open contexts are accepted.
+    context: SigningContext,
+    /// The message to sign, base64-encoded.
+    #[serde(with = "base64_bytes")]
+    msg: Vec<u8>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    sig_share: SignatureShare,
+}
+
+/// Returns our signature share of the message in an open context, with the key of a finalized
+/// session.
+async fn sign_share(
+    State(state): State<AppState>,
+    Json(req_body): Json<SignShareReq>,
+) -> Result<Json<SignShareResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    let node_idx = share_index(&state, &session).await?;
+    let context = open_context(req_body.context)?;
+    let sig_share = key_share.sign(context, &req_body.msg);
+    Ok(Json(SignShareResp {
+        node_idx,
+        sig_share,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareBatchReq {
+    /// The session of the key to sign with.
+    session_id: usize,
+    /// The context the signature is made in. Only open contexts are accepted.
+    context: SigningContext,
+    /// The messages to sign, base64-encoded.
+    #[serde(with = "base64_bytes::vec")]
+    msgs: Vec<Vec<u8>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct SignShareBatchResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    /// Our signature share of each message, in the same order.
+    sig_shares: Vec<SignatureShare>,
+}
+
+/// Returns our signature shares of many messages in an open context, with the key of a finalized
+/// session.
+async fn sign_share_batch(
+    State(state): State<AppState>,
+    Json(req_body): Json<SignShareBatchReq>,
+) -> Result<Json<SignShareBatchResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let key_share = session.key_share.as_ref().ok_or_else(|| {
+        bad_request(format!(
+            "We hold no share of the key of session #{}",
+            req_body.session_id
+        ))
+    })?;
+    if req_body.msgs.len() > MAX_BATCH_SIZE {
+        return Err(bad_request(format!(
+            "At most {} messages per batch",
+            MAX_BATCH_SIZE
+        )));
+    }
+    let node_idx = share_index(&state, &session).await?;
+    let context = open_context(req_body.context)?;
+    // Signing takes a scalar multiplication per message: it runs off the async runtime.
+    let key_share = key_share.clone();
+    let msgs = req_body.msgs;
+    let sig_shares = tokio::task::spawn_blocking(move || {
+        msgs.iter()
+            .map(|msg| key_share.sign(context, msg))
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(internal_error)?;
+    Ok(Json(SignShareBatchResp {
+        node_idx,
+        sig_shares,
+    }))
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconShareReq {
+    /// The session of the key the beacon runs on.
+    session_id: usize,
+    round: u64,
+    /// The previous round, or `None` in round 0.
+    prev: Option<BeaconRound>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+struct BeaconShareResp {
+    /// Our share index: our position in the committee.
+    node_idx: usize,
+    sig_share: SignatureShare,
+}
+
+/// Returns our signature share of a beacon round, i.e. of the round number and the previous
+/// round's signature in the `beacon` context. The previous round must be valid, so that rounds
+/// only ever extend a verifiable chain, and the round must be due: otherwise a caller could
+/// collect the randomness of future rounds. We never go back to a round before the latest one we
+/// signed.
+async fn beacon_share(
+    State(state): State<AppState>,
+    Json(req_body): Json<BeaconShareReq>,
+) -> Result<Json<BeaconShareResp>, HandlerError> {
+    let session = get_session(&state.db, req_body.session_id)?;
+    let (pub_key_set, key_share) = match (&session.pub_key_set, &session.key_share) {
+        (Some(pub_key_set), Some(key_share)) => (pub_key_set, key_share),
+        _ => {
+            return Err(bad_request(format!(
+                "We hold no share of the key of session #{}",
+                req_body.session_id
+            )))
+        }
+    };
+    let pk = pub_key_set.public_key();
+    let prev_signature = match &req_body.prev {
+        None if req_body.round == 0 => None,
+        Some(prev) if prev.round + 1 == req_body.round && prev.verify(&pk) => Some(&prev.signature),
+        _ => return Err(bad_request("Invalid previous beacon round")),
+    };
+    let schedule = state
+        .beacon
+        .ok_or_else(|| bad_request("The beacon is disabled"))?;
+    if !schedule.is_due(req_body.round, unix_time()) {
+        return Err(forbidden(format!(
+            "Beacon round {} is not due yet",
+            req_body.round
+        )));
+    }
+    let node_idx = share_index(&state, &session).await?;
+    {
+        let mut db = state.db.write().unwrap();
+        let session = db
+            .get_mut(&req_body.session_id)
+            .ok_or_else(|| bad_request(format!("Unknown session #{}", req_body.session_id)))?;
+        // The same round may be asked for again, e.g. if the orchestrator retries it.
+        match session.beacon_round {
+            Some(latest) if latest > req_body.round => {
+                return Err(conflict(format!(
+                    "We already signed beacon round {}",
+                    latest
+                )))
+            }
+            _ => session.beacon_round = Some(req_body.round),
+        }
+    }
+    save_session(&state, req_body.session_id).await?;
+    let msg = BeaconRound::message(req_body.round, prev_signature);
+    let sig_share = key_share.sign(SigningContext::Beacon, &msg);
+    Ok(Json(BeaconShareResp {
+        node_idx,
+        sig_share,
+    }))
+}
+
+/// Returns the context, unless it is reserved for the messages of its protocol. Signing
+/// arbitrary messages in it, e.g. future beacon rounds, would break that protocol.
+fn open_context(context: SigningContext) -> Result<SigningContext, HandlerError> {
+    if !context.is_open() {
+        return Err(bad_request(format!(
+            "The {} context is reserved",
+            context.tag()
+        )));
+    }
+    Ok(context)
+}
+
+/// Returns our share index in the session: our position in the committee.
+async fn share_index(state: &AppState, session: &Session) -> Result<usize, HandlerError> {
+    session
+        .node
+        .lock()
+        .await
+        .public_keys()
+        .keys()
+        .position(|id| *id == state.node_id)
+        .ok_or_else(|| internal_error("We hold a key share, but are not in the committee"))
+}
+
+fn get_session(db: &Db, session_id: usize) -> Result<Session, HandlerError> {
+    db.read()
+        .unwrap()
+        .get(&session_id)
+        .cloned()
+        .ok_or_else(|| bad_request(format!("Unknown session #{}", session_id)))
+}
+
+/// Fails with 409 if the session already exists: it may hold a key share we must not lose.
+fn check_new_session(db: &Db, session_id: usize) -> Result<(), HandlerError> {
+    if db.read().unwrap().contains_key(&session_id) {
+        return Err(conflict(format!("Session #{} already exists", session_id)));
+    }
+    Ok(())
+}
+
+/// Adds a new session, or fails with 409 if it already exists.
+fn insert_session(db: &Db, session_id: usize, session: Session) -> Result<(), HandlerError> {
+    match db.write().unwrap().entry(session_id) {
+        Entry::Occupied(_) => Err(conflict(format!("Session #{} already exists", session_id))),
+        Entry::Vacant(entry) => {
+            entry.insert(session);
+            Ok(())
+        }
+    }
+}
+
+fn update_session<F>(db: &Db, session_id: usize, f: F)
+where
+    F: FnOnce(&mut Session),
+{
+    if let Some(session) = db.write().unwrap().get_mut(&session_id) {
+        f(session);
+    }
+}
+
+/// Stores a snapshot of the session, if we have a state directory, so that we can resume it after
+/// a restart. The session's key generation must not be locked by the caller.
+async fn save_session(state: &AppState, session_id: usize) -> Result<(), HandlerError> {
+    let store = match &state.store {
+        Some(store) => store,
+        None => return Ok(()),
+    };
+    let session = get_session(&state.db, session_id)?;
+    let our_pk = state.sk.public_key();
+    let mut rng = rand::rngs::OsRng::new().expect("Could not open OS random number generator.");
+    let node = session
+        .node
+        .lock()
+        .await
+        .snapshot(&our_pk, &mut rng)
+        .map_err(internal_error)?;
+    let ser_node = bincode::serialize(&node).map_err(internal_error)?;
+    let key_share = session
+        .key_share
+        .as_ref()
+        .map(|key_share| key_share.encrypt(&our_pk, &mut rng))
+        .transpose()
+        .map_err(internal_error)?;
+    let snapshot = SessionSnapshot {
+        version: SESSION_SNAPSHOT_VERSION,
+        node: ser_node,
+        params: session.params,
+        parts: session.parts,
+        acks: session.acks,
+        pub_key_set: session.pub_key_set,
+        key_share,
+        faults: session.faults,
+        beacon_round: session.beacon_round,
+    };
+    let ser_snapshot = bincode::serialize(&snapshot).map_err(internal_error)?;
+    store
+        .save_session(session_id, &ser_snapshot)
+        .map_err(internal_error)
+}
+
+/// Restores a session from its serialized snapshot, given our secret key.
+fn restore_session(ser_session: &[u8], sk: &SecretKey) -> Result<Session, dkg::Error> {
+    let snapshot = SessionSnapshot::from_bytes(ser_session)?;
+    let node = KeyGenSnapshot::read(&mut &snapshot.node[..])?;
+    let node = AsyncKeyGen::restore(node, sk.clone())?;
+    let key_share = snapshot
+        .key_share
+        .map(|ct| KeyShare::decrypt(&ct, sk))
+        .transpose()?;
+    Ok(Session {
+        node: Arc::new(Mutex::new(node)),
+        params: snapshot.params,
+        parts: snapshot.parts,
+        acks: snapshot.acks,
+        pub_key_set: snapshot.pub_key_set,
+        key_share,
+        faults: snapshot.faults,
+        beacon_round: snapshot.beacon_round,
+    })
+}
+
+fn bad_request<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+fn forbidden<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::FORBIDDEN, err.to_string())
+}
+
+fn conflict<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::CONFLICT, err.to_string())
+}
+
+fn internal_error<E: ToString>(err: E) -> HandlerError {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// Returns the current time in seconds since the Unix epoch.
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The clock is before the Unix epoch.")
+        .as_secs()
+}
 
-    Json(FinalizeResp { is_success })
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|val| val.parse().ok())
+        .unwrap_or(default)
 }
 
 fn print_json<T>(t: &T, msg: &str)
@@ -0,0 +1,86 @@
+//! Persistence of the node key and the DKG sessions, so that a node that restarts in the middle of
+//! a key generation can resume it.
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use threshold_crypto::SecretKey;
+
+/// The file containing the seed of our long-term secret key.
+const NODE_KEY_FILE: &str = "node_key";
+/// The prefix of the session files, followed by the session ID.
+const SESSION_PREFIX: &str = "session_";
+
+/// A directory containing the seed of our secret key and a snapshot of every session.
+///
+/// The session snapshots contain our secret values encrypted to our own key, but the key seed is
+/// stored in the clear: the directory must only be readable by the node.
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+impl Store {
+    /// Opens the store in the given directory, creating it if necessary.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Store> {
+        fs::create_dir_all(&dir)?;
+        Ok(Store {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    /// Returns our long-term secret key. Its seed is generated and stored on first use, so that
+    /// the key survives restarts.
+    pub fn node_key(&self) -> io::Result<SecretKey> {
+        let path = self.dir.join(NODE_KEY_FILE);
+        let seed: [u8; 32] = match fs::read(&path) {
+            Ok(bytes) => bincode::deserialize(&bytes).map_err(invalid_data)?,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                let seed = rand::random();
+                let ser_seed = bincode::serialize(&seed).map_err(invalid_data)?;
+                self.write(&path, &ser_seed)?;
+                seed
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(StdRng::from_seed(seed).gen())
+    }
+
+    /// Replaces the stored serialized snapshot of the session.
+    pub fn save_session(&self, session_id: usize, ser_session: &[u8]) -> io::Result<()> {
+        let path = self.dir.join(format!("{}{}", SESSION_PREFIX, session_id));
+        self.write(&path, ser_session)
+    }
+
+    /// Returns the stored serialized snapshots of all sessions, by session ID.
+    pub fn load_sessions(&self) -> io::Result<Vec<(usize, Vec<u8>)>> {
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let session_id = match file_name
+                .to_str()
+                .and_then(|name| name.strip_prefix(SESSION_PREFIX))
+                .and_then(|id| id.parse().ok())
+            {
+                Some(session_id) => session_id,
+                None => continue, // Not a session file.
+            };
+            sessions.push((session_id, fs::read(entry.path())?));
+        }
+        Ok(sessions)
+    }
+
+    /// Writes the file atomically, so that a crash never leaves a partial snapshot behind.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, path)
+    }
+}
+
+fn invalid_data(err: bincode::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}